@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, Criterion, BatchSize};
+use nalgebra::{DMatrix, Point2};
+use ndhistogram::{Histogram, Hist2D, axis::VariableNoFlow, ndhistogram};
+
+use toymc::{Image, SimConfig};
+use toymc::random::random_in_circle;
+
+/// Previous implementation: variable-bin histogram including the gaps,
+/// rebuilt for every event.
+fn histogram_image(bins: &[f64], hits: &[Point2<f64>]) -> DMatrix<usize> {
+    let vbins    = VariableNoFlow::new(bins.to_vec()).unwrap();
+    let mut hist : Hist2D<_, _, usize> = ndhistogram!(vbins.clone(), vbins; usize);
+    for p in hits { hist.fill(&(p.x, p.y)); }
+
+    let n = bins.len() - 1;
+    let v : Vec<usize> =
+        hist.iter()
+            .enumerate()
+            .map   (|(i,v  )| (i/n, i.rem_euclid(n), v))
+            .filter(|(r,_,_)| (*r > 0) & (*r < n-1))
+            .filter(|(_,c,_)| (*c > 0) & (*c < n-1))
+            .filter(|(r,_,_)| (r-1).rem_euclid(3) == 0)
+            .filter(|(_,c,_)| (c-1).rem_euclid(3) == 0)
+            .map   (|(_,_,b)| b.value)
+            .copied()
+            .collect();
+    let n = n/3;
+    DMatrix::from_vec(n, n, v).transpose()
+}
+
+/// Hits of a typical event on the default geometry.
+fn event_hits(conf: &SimConfig) -> Vec<Point2<f64>> {
+    let n = (conf.sim_params.n_ie_ave() * conf.sim_params.light_yield / 2.0) as usize;
+    let r = -conf.geometry.sipm_plane.sipm_bins().first().unwrap();
+    (0..n).map(|_| random_in_circle(r)).collect()
+}
+
+fn image(c: &mut Criterion) {
+    let conf  = SimConfig::new("conf/test.toml").unwrap();
+    let sipms = &conf.geometry.sipm_plane;
+    let hits  = event_hits(&conf);
+
+    let mut group = c.benchmark_group("image");
+    group.bench_function("histogram_per_event", |b| {
+        b.iter(|| histogram_image(&sipms.sipm_bins(), &hits))
+    });
+    group.bench_function("reusable", |b| {
+        let mut img = Image::for_sipms(sipms).unwrap();
+        b.iter(|| {
+            img.reset();
+            for p in &hits { img.fill(p); }
+            img.finalize()
+        })
+    });
+    group.bench_function("reusable_fill_only", |b| {
+        b.iter_batched_ref(|| Image::for_sipms(sipms).unwrap(),
+                           |img| for p in &hits { img.fill(p); },
+                           BatchSize::SmallInput)
+    });
+    group.bench_function("reusable_finalize_only", |b| {
+        let mut img = Image::for_sipms(sipms).unwrap();
+        for p in &hits { img.fill(p); }
+        b.iter(|| img.finalize())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, image);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use nalgebra::{point, Point3};
+
+use toymc::{SimConfig, Simulator};
+use toymc::simulation::{generate_electrons, propagate_to_wire, propagate_light, is_shadowed, is_shadowed_by_wires};
+use toymc::random::{seed, uniform};
+
+/// Photons emitted per call when timing the photon loop.
+const N_PHOTONS: u64 = 10_000;
+
+fn default_conf() -> SimConfig {
+    SimConfig::new("conf/test.toml").unwrap()
+}
+
+/// Emission point of an electron drifting to the central wire.
+fn emission_point(conf: &SimConfig) -> Point3<f64> {
+    let wires  = &conf.geometry.wire_plane;
+    let first  = *wires.wire_pos().first().unwrap();
+    let (p, _) = propagate_to_wire(point!(0.3, 0.0), wires.wire_pitch, first, wires.wire_r, conf.sim_params.el_range);
+    p
+}
+
+fn light(c: &mut Criterion) {
+    seed(50);
+    let conf   = default_conf();
+    let wires  = conf.geometry.wire_plane.wire_pos();
+    let wire_r = conf.geometry.wire_plane.wire_r;
+    let p0     = emission_point(&conf);
+
+    let mut group = c.benchmark_group("light");
+    group.throughput(Throughput::Elements(N_PHOTONS));
+    group.bench_function("propagate_light", |b| {
+        b.iter(|| propagate_light(p0, 0.0, &wires, wire_r, &conf.geometry.meshes, 2.0 * N_PHOTONS as f64, conf.geometry.buffer, 0.0))
+    });
+    group.bench_function("propagate_light_el_delay", |b| {
+        b.iter(|| propagate_light(p0, 0.0, &wires, wire_r, &conf.geometry.meshes, 2.0 * N_PHOTONS as f64, conf.geometry.buffer, 20.0))
+    });
+    group.finish();
+}
+
+fn shadow(c: &mut Criterion) {
+    seed(50);
+    let conf   = default_conf();
+    let wires  = conf.geometry.wire_plane.wire_pos();
+    let wire_r = conf.geometry.wire_plane.wire_r;
+    let p0     = emission_point(&conf);
+    let pwire  = point!(*wires.iter().min_by(|a, b| (*a - p0.x).abs().total_cmp(&(*b - p0.x).abs())).unwrap(), p0.y, 0.0);
+    let rays   : Vec<(f64, f64)> = (0..N_PHOTONS).map(|_| (uniform(0.0, 1.0), uniform(0.0, std::f64::consts::TAU))).collect();
+
+    let mut group = c.benchmark_group("shadow");
+    group.throughput(Throughput::Elements(N_PHOTONS));
+    group.bench_function("is_shadowed", |b| {
+        b.iter(|| rays.iter().filter(|&&(cos_th, phi)| is_shadowed(&p0, &pwire, wire_r, cos_th, phi)).count())
+    });
+    group.bench_function("is_shadowed_by_wires", |b| {
+        b.iter(|| rays.iter().filter(|&&(cos_th, phi)| is_shadowed_by_wires(&p0, &wires, wire_r, cos_th, phi)).count())
+    });
+    group.finish();
+}
+
+fn electrons(c: &mut Criterion) {
+    seed(50);
+    let conf   = default_conf();
+    let params = &conf.sim_params;
+
+    let mut group = c.benchmark_group("electrons");
+    group.throughput(Throughput::Elements(params.n_ie_ave() as u64));
+    group.bench_function("generate_electrons", |b| {
+        b.iter(|| generate_electrons(point!(1.0, 2.0), params.n_ie_ave(), params.fano_factor, params.cloud_r))
+    });
+    group.finish();
+}
+
+/// Whole events on the default geometry, without and with the time response.
+fn event(c: &mut Criterion) {
+    seed(50);
+    let conf   = default_conf();
+    let timing = SimConfig::new("preset:timing").unwrap();
+
+    let mut group = c.benchmark_group("event");
+    group.sample_size(20);
+    group.bench_function("default", |b| {
+        let mut sim = Simulator::new(&conf).unwrap();
+        b.iter(|| sim.simulate(0, None))
+    });
+    group.bench_function("timing", |b| {
+        let mut sim = Simulator::new(&timing).unwrap();
+        b.iter(|| sim.simulate(0, None))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, light, shadow, electrons, event);
+criterion_main!(benches);
@@ -0,0 +1,331 @@
+use serde::Serialize;
+
+/// Value below which a fraction `q` of `sorted` falls, interpolating
+/// linearly between neighbours. `sorted` must be sorted and not empty.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let k  = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lo = k.floor() as usize;
+    let hi = k.ceil () as usize;
+    sorted[lo] + (k - lo as f64) * (sorted[hi] - sorted[lo])
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Root mean square, about zero.
+pub fn rms(values: &[f64]) -> f64 {
+    (values.iter().map(|v| v * v).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+/// Full width at half maximum of the distribution of `values`, from a
+/// histogram with Freedman-Diaconis bins. The half-maximum crossings are
+/// interpolated linearly between bin centres.
+pub fn fwhm(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n     = sorted.len();
+    if n < 2 { return 0.0 }
+    let iqr   = quantile(&sorted, 0.75) - quantile(&sorted, 0.25);
+    let (lo, hi) = (sorted[0], sorted[n - 1]);
+    if hi <= lo { return 0.0 }
+    let width = if iqr > 0.0 { 2.0 * iqr / (n as f64).cbrt() } else { (hi - lo) / 10.0 };
+    let nbins = ((hi - lo) / width).ceil().clamp(1.0, 1e4) as usize;
+    let width = (hi - lo) / nbins as f64;
+
+    let mut counts = vec![0.0f64; nbins];
+    for v in &sorted {
+        let k = (((v - lo) / width) as usize).min(nbins - 1);
+        counts[k] += 1.0;
+    }
+    let (kmax, &max) = counts.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap();
+    let half   = max / 2.0;
+    let centre = |k: f64| lo + (k + 0.5) * width;
+    // fractional bin index where the histogram crosses half maximum
+    let crossing = |k: usize, next: usize| {
+        let (a, b) = (counts[k], counts[next]);
+        k as f64 + (half - a) / (b - a) * (next as f64 - k as f64)
+    };
+    let left  = (0..kmax).rev().find(|&k| counts[k] < half).map_or(-0.5, |k| crossing(k, k + 1));
+    let right = (kmax+1..nbins).find(|&k| counts[k] < half).map_or(nbins as f64 - 0.5, |k| crossing(k, k - 1));
+    centre(right) - centre(left)
+}
+
+/// Position resolution of a set of residuals (reco - truth).
+#[derive(Debug, Clone, Serialize)]
+pub struct Resolution {
+    pub n     : usize,
+    pub bias_x: f64,
+    pub bias_y: f64,
+    pub rms_x : f64,
+    pub rms_y : f64,
+    pub rms_r : f64,
+    pub fwhm_x: f64,
+    pub fwhm_y: f64,
+    pub q50_r : f64,
+    pub q68_r : f64,
+    pub q90_r : f64,
+    pub q95_r : f64,
+}
+
+impl Resolution {
+    /// `None` if there are no residuals.
+    pub fn new(dx: &[f64], dy: &[f64]) -> Option<Self> {
+        if dx.is_empty() { return None }
+        let mut dr : Vec<f64> = dx.iter().zip(dy).map(|(x, y)| x.hypot(*y)).collect();
+        dr.sort_by(f64::total_cmp);
+        Some(Self{ n     : dx.len()
+                 , bias_x: mean(dx)
+                 , bias_y: mean(dy)
+                 , rms_x : rms(dx)
+                 , rms_y : rms(dy)
+                 , rms_r : rms(&dr)
+                 , fwhm_x: fwhm(dx)
+                 , fwhm_y: fwhm(dy)
+                 , q50_r : quantile(&dr, 0.50)
+                 , q68_r : quantile(&dr, 0.68)
+                 , q90_r : quantile(&dr, 0.90)
+                 , q95_r : quantile(&dr, 0.95)
+                 })
+    }
+}
+
+/// Variance divided by the squared mean.
+pub fn relative_variance(values: &[f64]) -> f64 {
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / values.len() as f64 / (m * m)
+}
+
+/// Relative FWHM, 2.355 σ/μ, of a relative variance.
+fn relative_fwhm(relative_variance: f64) -> f64 {
+    2.0 * (2.0 * 2f64.ln()).sqrt() * relative_variance.max(0.0).sqrt()
+}
+
+/// Energy resolution, as relative FWHM (2.355 σ/μ), and the contribution of
+/// each stage of the signal chain, from the truth fields: the fluctuation of
+/// the number of electrons (Fano), of the photons emitted per electron (EL
+/// gain), of their detection, including the geometric acceptance (PDE), and
+/// of the readout (electronics). Each contribution is the relative variance
+/// added by its stage and they add in quadrature to `raw`. Negative
+/// contributions from statistical fluctuations are reported as zero.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnergyResolution {
+    pub n          : usize,
+    pub raw        : f64,
+    pub corrected  : f64,
+    pub fano       : f64,
+    pub el_gain    : f64,
+    pub detection  : f64,
+    pub electronics: f64,
+}
+
+impl EnergyResolution {
+    /// `raw` is the total signal and `corrected` the energy estimate, both
+    /// per event like the truth counts.
+    pub fn new(n_electrons: &[f64], n_photons: &[f64], n_detected: &[f64], raw: &[f64], corrected: &[f64]) -> Self {
+        let r_e   = relative_variance(n_electrons);
+        let r_ph  = relative_variance(n_photons);
+        let r_det = relative_variance(n_detected);
+        let r_raw = relative_variance(raw);
+        Self{ n          : raw.len()
+            , raw        : relative_fwhm(r_raw)
+            , corrected  : relative_fwhm(relative_variance(corrected))
+            , fano       : relative_fwhm(r_e)
+            , el_gain    : relative_fwhm(r_ph  - r_e  )
+            , detection  : relative_fwhm(r_det - r_ph )
+            , electronics: relative_fwhm(r_raw - r_det)
+            }
+    }
+}
+
+/// Index of the bin of `u` among `n` equal bins spanning [`lo`, `hi`).
+pub fn bin(u: f64, lo: f64, hi: f64, n: usize) -> Option<usize> {
+    if !(lo..hi).contains(&u) { return None }
+    Some((((u - lo) / (hi - lo) * n as f64) as usize).min(n - 1))
+}
+
+/// Kolmogorov-Smirnov distance between the empirical distribution of
+/// `values` and the cumulative distribution `cdf`.
+pub fn ks_distance(values: &[f64], cdf: impl Fn(f64) -> f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len() as f64;
+    sorted.iter()
+          .enumerate()
+          .map(|(i, &v)| { let f = cdf(v); (f - i as f64 / n).max((i + 1) as f64 / n - f) })
+          .fold(0.0, f64::max)
+}
+
+/// Probability of a Kolmogorov-Smirnov distance of at least `d` between `n`
+/// values and the distribution they follow. Asymptotic, with Stephens'
+/// correction for finite `n`.
+pub fn ks_p_value(d: f64, n: usize) -> f64 {
+    let sqrt_n = (n as f64).sqrt();
+    let lambda = (sqrt_n + 0.12 + 0.11 / sqrt_n) * d;
+    if lambda < 0.3 { return 1.0 } // the series converges slowly, to 1
+    let terms  = (1..=100).map(|k| {
+        let k = k as f64;
+        2.0 * (-1f64).powf(k - 1.0) * (-2.0 * k * k * lambda * lambda).exp()
+    });
+    terms.sum::<f64>().clamp(0.0, 1.0)
+}
+
+/// Pearson's χ² of `observed` counts against `expected` ones. Bins expecting
+/// nothing are skipped.
+pub fn chi2(observed: &[f64], expected: &[f64]) -> f64 {
+    observed.iter()
+            .zip(expected)
+            .filter(|(_, e)| **e > 0.0)
+            .map(|(o, e)| (o - e).powi(2) / e)
+            .sum()
+}
+
+/// Probability of a χ² of at least `x` with `dof` degrees of freedom.
+pub fn chi2_p_value(x: f64, dof: usize) -> f64 {
+    upper_gamma(dof as f64 / 2.0, x / 2.0)
+}
+
+/// Logarithm of the gamma function, Lanczos approximation (g = 7).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 9] = [ 0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8
+                             , 771.323_428_777_653_1  , -176.615_029_162_140_6, 12.507_343_278_686_905
+                             , -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7
+                             ];
+    let x   = x - 1.0;
+    let sum = COEFFS[1..].iter().enumerate().fold(COEFFS[0], |s, (i, c)| s + c / (x + i as f64 + 1.0));
+    let t   = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Regularized upper incomplete gamma function Q(a, x), from its series
+/// below `a + 1` and its continued fraction above (Numerical Recipes 6.2).
+fn upper_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 { return 1.0 }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum) = (1.0 / a, 1.0 / a);
+        for n in 1..500 {
+            term *= x / (a + n as f64);
+            sum  += term;
+            if term < sum * 1e-15 { break }
+        }
+        1.0 - sum * prefactor
+    } else {
+        // modified Lentz's method
+        let tiny  = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..500 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d  = an * d + b;
+            if d.abs() < tiny { d = tiny }
+            c  = b + an / c;
+            if c.abs() < tiny { c = tiny }
+            d  = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 { break }
+        }
+        prefactor * h
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use float_eq::assert_float_eq;
+    use crate::random::normal;
+
+    #[test]
+    fn quantiles() {
+        let v = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_float_eq!(quantile(&v, 0.0  ), 1.0, ulps<=1);
+        assert_float_eq!(quantile(&v, 0.5  ), 3.0, ulps<=1);
+        assert_float_eq!(quantile(&v, 0.625), 3.5, ulps<=1);
+        assert_float_eq!(quantile(&v, 1.0  ), 5.0, ulps<=1);
+    }
+
+    #[test]
+    fn gaussian_fwhm() {
+        crate::random::seed(2355);
+        let sigma = 1.5;
+        let v : Vec<f64> = (0..200_000).map(|_| normal(0.0, sigma)).collect();
+        assert_float_eq!(fwhm(&v), 2.3548 * sigma, rmax<=0.03);
+    }
+
+    #[test]
+    fn degenerate_fwhm() {
+        assert_eq!(fwhm(&[]), 0.0);
+        assert_eq!(fwhm(&[1.0, 1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn resolution() {
+        let res = Resolution::new(&[3.0, -3.0, 3.0, -3.0], &[4.0, 4.0, -4.0, -4.0]).unwrap();
+        assert_eq!(res.n, 4);
+        assert_float_eq!(res.bias_x, 0.0, abs<=1e-12);
+        assert_float_eq!(res.rms_x , 3.0, abs<=1e-12);
+        assert_float_eq!(res.rms_r , 5.0, abs<=1e-12);
+        assert_float_eq!(res.q68_r , 5.0, abs<=1e-12);
+        assert!(Resolution::new(&[], &[]).is_none());
+    }
+
+    #[test]
+    fn energy_stages() {
+        crate::random::seed(100);
+        // Poisson stages: relative variances 1/100, +1/1000 and +1/100
+        let n_e   : Vec<f64> = (0..100_000).map(|_| crate::random::poisson(100.0)).collect();
+        let n_ph  : Vec<f64> = n_e .iter().map(|&n| crate::random::poisson(10.0 * n)).collect();
+        let n_det : Vec<f64> = n_ph.iter().map(|&n| crate::random::binomial(n as u64, 0.1) as f64).collect();
+        let res   = EnergyResolution::new(&n_e, &n_ph, &n_det, &n_det, &n_det);
+        let f     = |r: f64| relative_fwhm(r);
+        assert_float_eq!(res.fano       , f(0.01 ), rmax<=0.03);
+        assert_float_eq!(res.el_gain    , f(0.001), rmax<=0.2 );
+        assert_float_eq!(res.detection  , f(0.009), rmax<=0.05);
+        assert_float_eq!(res.electronics, 0.0     , abs <=1e-12);
+        assert_float_eq!(res.raw, f(0.01 + 0.001 + 0.009), rmax<=0.03);
+    }
+
+    #[test]
+    fn bins() {
+        assert_eq!(bin(-1.0 , -1.0, 1.0, 4), Some(0));
+        assert_eq!(bin( 0.0 , -1.0, 1.0, 4), Some(2));
+        assert_eq!(bin( 0.99, -1.0, 1.0, 4), Some(3));
+        assert_eq!(bin( 1.0 , -1.0, 1.0, 4), None);
+        assert_eq!(bin(f64::NAN, -1.0, 1.0, 4), None);
+    }
+
+    #[test]
+    fn gamma_functions() {
+        assert_float_eq!(ln_gamma(5.0), 24f64.ln(), rmax<=1e-12);
+        assert_float_eq!(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), rmax<=1e-12);
+        // 2 degrees of freedom: exp(-x/2)
+        for x in [0.1, 1.0, 3.0, 10.0, 40.0] {
+            assert_float_eq!(chi2_p_value(x, 2), (-x / 2.0).exp(), rmax<=1e-9);
+        }
+        assert_float_eq!(chi2_p_value(3.841_458_820_694_124, 1), 0.05, rmax<=1e-9);
+        assert_float_eq!(chi2_p_value(18.307_038_053_275_146, 10), 0.05, rmax<=1e-9);
+        assert_eq!(chi2_p_value(0.0, 3), 1.0);
+    }
+
+    #[test]
+    fn goodness_of_fit() {
+        crate::random::seed(48);
+        let values : Vec<f64> = (0..10_000).map(|_| crate::random::uniform(0.0, 1.0)).collect();
+        let d      = ks_distance(&values, |x| x);
+        assert!(ks_p_value(d, values.len()) > 0.01, "d {d}");
+        let d      = ks_distance(&values, |x| x * x);
+        assert!(ks_p_value(d, values.len()) < 1e-6, "d {d}");
+        assert_float_eq!(ks_distance(&[0.5], |x| x), 0.5, ulps<=1);
+
+        let expected = [2500.0; 4];
+        let counts   = (0..4).map(|i| values.iter().filter(|&&v| bin(v, 0.0, 1.0, 4) == Some(i)).count() as f64).collect::<Vec<_>>();
+        assert!(chi2_p_value(chi2(&counts, &expected), 3) > 0.01);
+        assert_float_eq!(chi2(&[1.0, 3.0, 5.0], &[2.0, 3.0, 0.0]), 0.5, ulps<=1);
+    }
+}
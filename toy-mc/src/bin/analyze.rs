@@ -0,0 +1,192 @@
+use std::io;
+use std::io::Write;
+use std::fs::{File, create_dir_all};
+use std::path::Path;
+use std::collections::HashMap;
+use nalgebra::point;
+use clap::Parser;
+use serde::Serialize;
+
+use toymc::{SimConfig, Lut, Event};
+use toymc::io::{read_events, read_table, Writer};
+use toymc::analysis::{Resolution, EnergyResolution, bin, mean, rms};
+use toymc::reco::{Response, SolidAngle, EnergyEstimator, calibrate};
+
+
+/// Summarizes a reconstructed dataset: overall resolution, resolution vs
+/// radius, bias maps, per-event charges and energies, and the energy
+/// resolution.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+
+    /// Event file (images.csv or images.feather)
+    #[arg(short, long)]
+    input: String,
+
+    /// Reconstructed positions, reco.csv next to the input by default
+    #[arg(short, long)]
+    reco: Option<String>,
+
+    /// Configuration of the run, run.conf next to the input by default
+    #[arg(short, long)]
+    conf: Option<String>,
+
+    /// Output directory, analysis/ next to the input by default
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Radial bins up to the EL radius
+    #[arg(long, default_value_t=8)]
+    r_bins: usize,
+
+    /// Bins along x and y of the bias map, over the EL region
+    #[arg(long, default_value_t=8)]
+    xy_bins: usize,
+
+    /// Light response lookup table for the energy correction, the analytic
+    /// solid angle by default
+    #[arg(long)]
+    lut: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Charge {
+    mean: f64,
+    rms : f64,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    n_events  : usize,
+    n_failed  : usize,
+    resolution: Option<Resolution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    combined  : Option<Resolution>,
+    sipm_q    : Charge,
+    wire_q    : Charge,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wire_adc  : Option<Charge>,
+    energy    : EnergyResolution,
+}
+
+/// Residuals of one event.
+struct Residual {
+    x0: f64,
+    y0: f64,
+    dx: f64,
+    dy: f64,
+}
+
+fn charge(values: &[f64]) -> Charge {
+    let m = mean(values);
+    let centred : Vec<f64> = values.iter().map(|v| v - m).collect();
+    Charge{mean: m, rms: rms(&centred)}
+}
+
+fn resolution(residuals: &[&Residual]) -> Option<Resolution> {
+    let dx : Vec<f64> = residuals.iter().map(|r| r.dx).collect();
+    let dy : Vec<f64> = residuals.iter().map(|r| r.dy).collect();
+    Resolution::new(&dx, &dy)
+}
+
+/// Columns of a resolution, NaN if there are no entries.
+fn resolution_row(res: &Option<Resolution>) -> String {
+    match res {
+        Some(r) => format!("{} {} {} {} {}", r.n, r.bias_x, r.bias_y, r.rms_r, r.q68_r),
+        None    => "0 NaN NaN NaN NaN".to_owned(),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args   = Cli::parse();
+    let dir    = Path::new(&args.input).parent().unwrap_or(Path::new("."));
+    let reco   = args.reco  .unwrap_or_else(|| dir.join("reco.csv").to_str().unwrap().to_owned());
+    let conf   = args.conf  .unwrap_or_else(|| dir.join("run.conf").to_str().unwrap().to_owned());
+    let output = args.output.map_or_else(|| dir.join("analysis"), Into::into);
+    let conf   = SimConfig::new(&conf).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    conf.warnings.iter().for_each(|w| eprintln!("warning: {w}"));
+    let el_r   = conf.geometry.el_gap.el_r;
+    create_dir_all(&output)?;
+
+    let table  = read_table(&reco, Writer::Csv)?;
+    let column = |name: &str| table.names.iter().position(|n| n == name);
+    let (Some(ievt), Some(ix0), Some(iy0), Some(ix), Some(iy), Some(idx), Some(idy)) =
+        (column("event"), column("x0"), column("y0"), column("x"), column("y"), column("dx"), column("dy")) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{reco}: missing event, x0, y0, x, y, dx or dy")))
+    };
+    let reco_pos : HashMap<usize, _> = table.rows.iter().map(|r| (r[ievt] as usize, point!(r[ix], r[iy]))).collect();
+    let residuals : Vec<Residual> =
+        table.rows.iter()
+             .map(|r| Residual{x0: r[ix0], y0: r[iy0], dx: r[idx], dy: r[idy]})
+             .collect();
+    let valid : Vec<&Residual> = residuals.iter().filter(|r| r.dx.is_finite() && r.dy.is_finite()).collect();
+    let combined = column("dxc").zip(column("dyc")).and_then(|(ixc, iyc)| {
+        let dx : Vec<f64> = table.rows.iter().map(|r| r[ixc]).filter(|v| v.is_finite()).collect();
+        let dy : Vec<f64> = table.rows.iter().map(|r| r[iyc]).filter(|v| v.is_finite()).collect();
+        Resolution::new(&dx, &dy)
+    });
+
+    // resolution vs radius
+    let mut file = File::create(output.join("resolution_vs_r.csv"))?;
+    writeln!(file, "r_lo r_hi n bias_x bias_y rms_r q68_r")?;
+    for k in 0..args.r_bins {
+        let (lo, hi) = (el_r * k as f64 / args.r_bins as f64, el_r * (k + 1) as f64 / args.r_bins as f64);
+        let in_bin : Vec<&Residual> = valid.iter().copied()
+                                           .filter(|r| bin(r.x0.hypot(r.y0), 0.0, el_r, args.r_bins) == Some(k))
+                                           .collect();
+        writeln!(file, "{lo} {hi} {}", resolution_row(&resolution(&in_bin)))?;
+    }
+
+    // bias map
+    let mut file = File::create(output.join("bias_map.csv"))?;
+    writeln!(file, "x_lo x_hi y_lo y_hi n bias_x bias_y rms_r q68_r")?;
+    let edge = |k: usize| -el_r + 2.0 * el_r * k as f64 / args.xy_bins as f64;
+    for j in 0..args.xy_bins {
+        for i in 0..args.xy_bins {
+            let in_cell : Vec<&Residual> = valid.iter().copied()
+                                                .filter(|r| bin(r.x0, -el_r, el_r, args.xy_bins) == Some(i)
+                                                         && bin(r.y0, -el_r, el_r, args.xy_bins) == Some(j))
+                                                .collect();
+            writeln!(file, "{} {} {} {} {}", edge(i), edge(i+1), edge(j), edge(j+1), resolution_row(&resolution(&in_cell)))?;
+        }
+    }
+
+    // charges and energy, corrected at the reconstructed position
+    let layout   = conf.geometry.sipm_plane.layout()?;
+    let response : Box<dyn Response> = match &args.lut {
+        Some(filename) => Box::new(Lut::from_file(filename)?),
+        None           => Box::new(SolidAngle::new(&layout, conf.geometry.buffer)),
+    };
+    let estimator = EnergyEstimator::new(Some(response));
+    let events   = read_events(&args.input)?;
+    let events   : Vec<_> = events.into_iter().filter(|e| reco_pos.get(&e.number).is_some_and(|p| p.x.is_finite())).collect();
+    let light    : Vec<f64> = events.iter().map(|e| estimator.light(&e.img, &reco_pos[&e.number])).collect();
+    let scale    = calibrate(&light, conf.sim_params.dep_energy);
+    let energy   : Vec<f64> = light.iter().map(|l| l * scale).collect();
+    let sipm_q   : Vec<f64> = events.iter().map(|e| e.img.sum() as f64).collect();
+    let wire_q   : Vec<f64> = events.iter().map(|e| e.wire_q.iter().sum::<usize>() as f64).collect();
+    let wire_adc : Option<Vec<f64>> = events.iter()
+                                            .map(|e| e.wire_adc.as_ref().map(|a| a.iter().sum::<usize>() as f64))
+                                            .collect();
+    let mut file = File::create(output.join("charges.csv"))?;
+    writeln!(file, "event energy sipm_q wire_q{}", if wire_adc.is_some() { " wire_adc" } else { "" })?;
+    for (k, e) in events.iter().enumerate() {
+        write!(file, "{} {} {} {}", e.number, energy[k], sipm_q[k], wire_q[k])?;
+        if let Some(adc) = &wire_adc { write!(file, " {}", adc[k])?; }
+        writeln!(file)?;
+    }
+
+    let truth   = |f: fn(&Event) -> usize| events.iter().map(|e| f(e) as f64).collect::<Vec<_>>();
+    let summary = Summary{ n_events  : residuals.len()
+                         , n_failed  : residuals.len() - valid.len()
+                         , resolution: resolution(&valid)
+                         , combined
+                         , sipm_q    : charge(&sipm_q)
+                         , wire_q    : charge(&wire_q)
+                         , wire_adc  : wire_adc.as_deref().map(charge)
+                         , energy    : EnergyResolution::new(&truth(|e| e.n_electrons), &truth(|e| e.n_photons), &truth(|e| e.n_detected), &sipm_q, &energy)
+                         };
+    let json = serde_json::to_string_pretty(&summary).map_err(io::Error::other)?;
+    File::create(output.join("summary.json"))?.write_all((json + "\n").as_bytes())
+}
@@ -1,22 +1,37 @@
 use std::io;
+use std::io::Write;
 use std::path::Path;
-use std::fs::{create_dir, File};
-use nalgebra::{point, Point2, Rotation2, DMatrix};
+use std::fs::{create_dir, create_dir_all, File};
 use indicatif::ProgressBar;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use toymc::{Image, Event, SimConfig};
+use toymc::{SimConfig, Simulator, Sweep, Scalar};
 use toymc::io::write_conf;
-use toymc::io::{writer, write_img_1d, Writer};
-use toymc::simulation::{generate_el_position, generate_electrons, propagate_to_wire, propagate_light};
+use toymc::io::{writer, waveform_writer, photon_writer, Writer};
+use toymc::random::{seed, current_seed, MAX_SEED};
 
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct CLI {
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
 
-    #[arg(short, long)]
-    conf: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Configuration file, read on top of the preset if both are given
+    #[arg(short, long, required_unless_present = "preset")]
+    conf: Option<String>,
+
+    /// Built-in configuration: default, timing
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Override a configuration entry, e.g. sim_params.light_yield=50. Takes
+    /// precedence over variables such as TOYMC_SIM_PARAMS__LIGHT_YIELD=50
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<String>,
 
     #[arg(short, long)]
     nevt: Option<usize>,
@@ -29,44 +44,118 @@ struct CLI {
 
     #[arg(long, action)]
     detailed: bool,
+
+    /// Write photon-level truth for this fraction of the events
+    #[arg(long, value_parser = fraction)]
+    photons: Option<f64>,
+
+    /// Seed of the random generator, overriding the configuration
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(..=MAX_SEED))]
+    seed: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Describe the configuration files
+    Config(ConfigArgs),
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+struct ConfigArgs {
+    /// Print the default configuration, with every option described
+    #[arg(long, action)]
+    print_default: bool,
+
+    /// Print the JSON Schema of the configuration files
+    #[arg(long, action)]
+    schema: bool,
+
+    /// Rewrite a configuration file in the current version, keeping its comments
+    #[arg(long, value_name = "FILE")]
+    migrate: Option<String>,
+}
+
+fn config(args: &ConfigArgs) -> io::Result<()> {
+    if let Some(filename) = &args.migrate {
+        let changes = SimConfig::migrate_file(filename)?;
+        changes.iter().for_each(|c| eprintln!("{filename}: {c}"));
+        return Ok(())
+    }
+    let contents = if args.print_default { SimConfig::default_toml().to_owned() }
+                   else                  { SimConfig::json_schema() + "\n"     };
+    io::stdout().write_all(contents.as_bytes())
 }
 
 fn main() -> io::Result<()> {
-    let args = CLI::parse();
-    let conf = SimConfig::new(&args.conf)
-                         .unwrap()
-                         .overrides(args.nevt, args.output);
+    let args = Cli::parse();
+    if let Some(Command::Config(c)) = &args.command { return config(c) }
+    let srcs : Vec<String> = args.preset.iter().map(|p| format!("preset:{p}")).chain(args.conf.clone()).collect();
+    let conf = SimConfig::load(&srcs, &args.set)
+                         .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+                         .overrides(args.nevt, args.output.clone());
+    conf.warnings.iter().for_each(|w| eprintln!("warning: {w}"));
+    let conf = if args.detailed { conf.enable_detailed()? } else { conf };
+    let conf = SimConfig{seed: Some(args.seed.or(conf.seed).unwrap_or_else(current_seed)), ..conf};
+    match conf.sweep.clone() {
+        Some(sweep) => run_sweep(conf, &sweep, &args),
+        None        => run(&conf, &args),
+    }
+}
+
+/// Runs each point of the sweep in its own directory under the output,
+/// with consecutive seeds, and lists the points in `sweep.csv`.
+fn run_sweep(conf: SimConfig, sweep: &Sweep, args: &Cli) -> io::Result<()> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let points  = sweep.points().map_err(invalid)?;
+    let path    = Path::new(&conf.output).to_owned();
+    create_dir_all(&path)?;
+    write_conf(path.join("run.conf").to_str().unwrap(), &conf)?;
+
+    let mut index = File::create(path.join("sweep.csv"))?;
+    let keys      = sweep.parameters.iter().map(|p| p.key.as_str()).collect::<Vec<_>>();
+    writeln!(index, "directory {}", keys.join(" "))?;
+
+    let first_seed = conf.seed.unwrap();
+    let base       = SimConfig{sweep: None, ..conf};
+    for (i, point) in points.into_iter().enumerate() {
+        let directory = format!("point_{i:03}");
+        let output    = path.join(&directory).to_str().unwrap().to_owned();
+        let values    = point.iter().map(|(_, v)| v.to_string()).collect::<Vec<_>>();
+        writeln!(index, "{directory} {}", values.join(" "))?;
+
+        let point = point.into_iter().chain([("output".to_owned(), Scalar::Text(output))]).collect::<Vec<_>>();
+        let conf  = base.with_values(&point).map_err(invalid)?;
+        let seed  = first_seed.checked_add(i as u64).filter(|s| *s <= MAX_SEED)
+                              .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("seed of point {i} above the largest of {MAX_SEED}")))?;
+        let conf  = SimConfig{seed: Some(seed), ..conf};
+        run(&conf, args)?;
+    }
+    Ok(())
+}
+
+fn run(conf: &SimConfig, args: &Cli) -> io::Result<()> {
+    seed(conf.seed.unwrap());
     let path = Path::new(&conf.output);
     if !path.exists() { create_dir(path)?; }
 
-    let filename_img  = "images.".to_string() + match args.format {
+    let extension     = match args.format {
         Writer::Csv     =>     "csv",
         Writer::Feather => "feather",
     };
-    let filename_img  = path.join(        &filename_img).to_str().unwrap().to_owned();
+    let filename_img  = path.join(format!(   "images.{extension}")).to_str().unwrap().to_owned();
+    let filename_wf   = path.join(format!("waveforms.{extension}")).to_str().unwrap().to_owned();
     let filename_conf = path.join(           "run.conf").to_str().unwrap().to_owned();
-    let filename_fine = path.join("detailed_images.csv").to_str().unwrap().to_owned();
+    let filename_ph   = path.join(    "photons.feather").to_str().unwrap().to_owned();
 
-    let wires      = &conf.geometry.wire_plane;
-    let sipms      = &conf.geometry.sipm_plane;
-    let elgap      = &conf.geometry.el_gap;
-    let params     = &conf.sim_params;
-    let all_wires  = wires.wire_pos();
-    let first_wire = all_wires.first().unwrap().clone();
-    let rotation   = Rotation2::new(-wires.wire_rotation);
-    let sipm_bins  = sipms.sipm_bins();
-
-    write_conf(&filename_conf, &conf)?;
-    let mut write_event = writer(&filename_img, args.format, &conf);
-    let mut file_fine   = if args.detailed { Some(File::create(filename_fine)?) } else { None };
-
-
-    let edge   = sipm_bins.first().unwrap();
-    let n_fine = 100;
-    let fine_bins : Vec<f64> =
-        (0..=n_fine).into_iter()
-        .map(|i| edge - (i as f64 / n_fine as f64)*2.0*edge)
-        .collect();
+    write_conf(&filename_conf, conf)?;
+    let mut sim = Simulator::new(conf)?;
+    let mut write_event = writer(&filename_img, args.format, conf, sim.layout())?;
+    let mut write_wfs   = conf.timing.as_ref().map(|_| waveform_writer(&filename_wf, args.format, conf)).transpose()?;
+    let mut write_ph    = args.photons.map(|_| photon_writer(&filename_ph, conf)).transpose()?;
+    // events with photon truth are picked apart from the simulation, so
+    // that recording them does not change the events
+    let mut pick = ChaCha8Rng::seed_from_u64(conf.seed.unwrap());
 
     let bar      = ProgressBar::new(conf.n_events as u64);
     let flushmod = (conf.n_events / 100).max(1);
@@ -75,33 +164,22 @@ fn main() -> io::Result<()> {
             bar.inc(flushmod as u64);
         }
 
-        let mut img      = Image::new(&sipms.sipm_bins());
-        let mut img_fine = if args.detailed { Some(Image::new(&fine_bins)) } else { None };
-        let mut wire_q   = vec![0usize; wires.n_wires];
-        let evt_pos      = generate_el_position(elgap.el_r);
-        let ps           = generate_electrons(evt_pos, params.n_ie_ave(), params.fano_factor, params.cloud_r);
-        for p0 in ps {
-            let (p1, iwire) = propagate_to_wire(p0, wires.wire_pitch, first_wire, wires.wire_r, params.el_range);
-            wire_q[iwire] += 1;
-            let wire = all_wires.get(iwire).unwrap();
-            let wire = point!(*wire, p0.y, 0.0);
-            let hits = propagate_light(p1, wire, params.light_yield, conf.geometry.buffer, wires.wire_r);
-            let hits = hits.iter().map(|h| rotation * h).collect::<Vec<Point2<_>>>();
-            for hit in &hits {
-                img.fill(hit);
-                if args.detailed { img_fine.as_mut().unwrap().fill(hit); }
-            }
-        }
-        let event = Event{number: ievt, position: evt_pos, wire_q, img: img.finalize()};
+        let mut photons  = Vec::new();
+        let keep_photons = args.photons.is_some_and(|f| pick.random::<f64>() < f);
+        let event        = sim.simulate(ievt, keep_photons.then_some(&mut photons));
         write_event(&event)?;
-
-        if args.detailed {
-            let n = fine_bins.len() - 1;
-            let m = DMatrix::from_vec(n, n, img_fine.unwrap().data()).transpose();
-            write_img_1d(file_fine.as_mut().unwrap(), &m)?;
+        if let Some(write_wfs) = write_wfs.as_mut() { write_wfs(&event)?; }
+        if keep_photons {
+            if let Some(write_ph) = write_ph.as_mut() { write_ph(ievt, &photons)?; }
         }
     }
     bar.finish();
 
     Ok(())
 }
+
+/// Parses a fraction, within [0, 1].
+fn fraction(s: &str) -> Result<f64, String> {
+    let f : f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&f) { Ok(f) } else { Err(format!("{f} is not within [0, 1]")) }
+}
@@ -0,0 +1,51 @@
+use std::io;
+use clap::Parser;
+
+use toymc::{SimConfig, Simulator, Lut};
+
+
+/// Builds a light response lookup table: mean and variance of each SiPM
+/// signal for events on a grid of positions.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+
+    /// Configuration file, read on top of the preset if both are given
+    #[arg(short, long, required_unless_present = "preset")]
+    conf: Option<String>,
+
+    /// Built-in configuration: default, timing
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Override a configuration entry, as in generate
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<String>,
+
+    /// Events per node
+    #[arg(short, long, default_value_t=100)]
+    nevt: usize,
+
+    /// Nodes along each axis
+    #[arg(short, long, default_value_t=41)]
+    bins: usize,
+
+    /// Half-width of the grid, the SiPM plane extent by default
+    #[arg(short, long)]
+    extent: Option<f64>,
+
+    #[arg(short, long, default_value="lut.feather")]
+    output: String,
+}
+
+fn main() -> io::Result<()> {
+    let args    = Cli::parse();
+    let sources : Vec<String> = args.preset.iter().map(|p| format!("preset:{p}")).chain(args.conf.clone()).collect();
+    let conf    = SimConfig::load(&sources, &args.set).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    conf.warnings.iter().for_each(|w| eprintln!("warning: {w}"));
+    if let Some(seed) = conf.seed { toymc::random::seed(seed); }
+    let mut sim = Simulator::new(&conf)?;
+    let extent  = args.extent.unwrap_or_else(|| sim.layout().extent());
+    let lut     = Lut::build(&mut sim, args.bins, extent, args.nevt);
+    lut.write(&args.output)
+}
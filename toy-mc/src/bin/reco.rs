@@ -0,0 +1,132 @@
+use std::io;
+use std::io::Write;
+use std::fs::File;
+use std::path::Path;
+use clap::{Parser, ValueEnum};
+use nalgebra::Point2;
+
+use toymc::{SimConfig, Lut};
+use toymc::io::read_events;
+use toymc::reco::{Barycenter, LikelihoodFit, SolidAngle, Response, WireCombination, wire_centroid};
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum Method {
+    Barycenter,
+    Likelihood,
+}
+
+/// Reconstructs the position of every event of a dataset written by
+/// `generate` and writes it with the truth and the residuals.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+
+    /// Event file (images.csv or images.feather)
+    #[arg(short, long)]
+    input: String,
+
+    /// Configuration of the run, run.conf next to the input by default
+    #[arg(short, long)]
+    conf: Option<String>,
+
+    /// Output file, reco.csv next to the input by default
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Ignore channels below this number of counts
+    #[arg(short, long, default_value_t=0)]
+    threshold: usize,
+
+    /// Use only the N x N sensors around the brightest one
+    #[arg(short, long)]
+    window: Option<usize>,
+
+    #[arg(short, long, value_enum, default_value_t=Method::Barycenter)]
+    method: Method,
+
+    /// Light response lookup table for the likelihood fit, the analytic
+    /// solid angle by default
+    #[arg(long)]
+    lut: Option<String>,
+
+    /// Combine with the wire charge centroid, assuming this SiPM resolution
+    /// (mm) per axis
+    #[arg(long)]
+    wires: Option<f64>,
+}
+
+/// Root mean square of the finite values.
+fn rms(values: &[f64]) -> f64 {
+    let finite : Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    (finite.iter().map(|v| v * v).sum::<f64>() / finite.len() as f64).sqrt()
+}
+
+fn main() -> io::Result<()> {
+    let args   = Cli::parse();
+    let dir    = Path::new(&args.input).parent().unwrap_or(Path::new("."));
+    let conf   = args.conf  .unwrap_or_else(|| dir.join("run.conf").to_str().unwrap().to_owned());
+    let output = args.output.unwrap_or_else(|| dir.join("reco.csv").to_str().unwrap().to_owned());
+    let conf   = SimConfig::new(&conf).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    conf.warnings.iter().for_each(|w| eprintln!("warning: {w}"));
+
+    let layout   = conf.geometry.sipm_plane.layout()?;
+    let rotation = conf.geometry.wire_plane.rotation();
+    let algo     = Barycenter::new(args.threshold, args.window);
+    let response : Box<dyn Response> = match &args.lut {
+        Some(filename) => Box::new(Lut::from_file(filename)?),
+        None           => Box::new(SolidAngle::new(&layout, conf.geometry.buffer)),
+    };
+    let fitter   = LikelihoodFit{ seed: algo, ..LikelihoodFit::new(response) };
+    let events   = read_events(&args.input)?;
+    let wire_pos = conf.geometry.wire_plane.wire_pos();
+    let combine  = args.wires.map(|sigma| WireCombination::for_wires(&conf.geometry.wire_plane, sigma));
+
+    let mut file   = File::create(&output)?;
+    let mut failed = 0;
+    let mut dr_sipm     = Vec::new();
+    let mut dr_combined = Vec::new();
+    write!(file, "event x0 y0 x y dx dy dr")?;
+    if args.method == Method::Likelihood { write!(file, " n_photons deviance ndof iterations converged")?; }
+    if combine.is_some()                 { write!(file, " x_wire xc yc dxc dyc drc")?; }
+    writeln!(file)?;
+    for event in events {
+        // truth in the SiPM frame
+        let truth = rotation * event.position;
+        let (reco, fit) = match args.method {
+            Method::Barycenter => (algo.reconstruct(&event.img, &layout), None),
+            Method::Likelihood => {
+                let fit = fitter.fit(&event.img, &layout);
+                (fit.as_ref().map(|f| f.position), fit)
+            }
+        };
+        if reco.is_none() { failed += 1; }
+        let reco  = reco.unwrap_or(Point2::new(f64::NAN, f64::NAN));
+        let d     = reco - truth;
+        write!(file, "{} {} {} {} {} {} {} {}", event.number, truth.x, truth.y, reco.x, reco.y, d.x, d.y, d.norm())?;
+        match (args.method, fit) {
+            (Method::Likelihood, Some(f)) => write!(file, " {} {} {} {} {}", f.n_photons, f.deviance, f.ndof, f.iterations, f.converged as u8)?,
+            (Method::Likelihood, None   ) => write!(file, " NaN NaN 0 0 0")?,
+            _                             => (),
+        }
+        dr_sipm.push(d.norm());
+        if let Some(combine) = &combine {
+            // digitized charge if available
+            let q      = event.wire_adc.as_ref().unwrap_or(&event.wire_q);
+            let x_wire = wire_centroid(q, &wire_pos).unwrap_or(f64::NAN);
+            let pc     = combine.combine(&reco, x_wire);
+            let dc     = pc - truth;
+            write!(file, " {} {} {} {} {} {}", x_wire, pc.x, pc.y, dc.x, dc.y, dc.norm())?;
+            dr_combined.push(dc.norm());
+        }
+        writeln!(file)?;
+    }
+    if failed > 0 {
+        eprintln!("{failed} events could not be reconstructed");
+    }
+    if combine.is_some() {
+        let (sipm, combined) = (rms(&dr_sipm), rms(&dr_combined));
+        println!("RMS dr: SiPMs {sipm:.3} mm, SiPMs + wires {combined:.3} mm ({:+.1}%)", 100.0 * (combined / sipm - 1.0));
+    }
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use std::fs::read_to_string;
+use std::io;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use derive_new::new;
+use nalgebra::DMatrix;
+
+use crate::random::{binomial, poisson};
+
+/// Detector calibration maps, applied to the SiPM image and used by the wire
+/// readout.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Calibration {
+    /// SiPM map, CSV or TOML: id, alive, gain, pde, dark_rate, relative to
+    /// the configuration file. Ideal sensors if not given
+    #[serde(default)]
+    pub sipm_map   : Option<String>,
+    /// Wire map, CSV or TOML: id, alive, gain, relative to the configuration
+    /// file. Ideal wires if not given
+    #[serde(default)]
+    pub wire_map   : Option<String>,
+    /// Integration time of the dark counts, ns
+    #[serde(default)]
+    pub dark_window: f64,
+}
+
+impl Calibration {
+    pub fn load(&self, n_sipms: usize, n_wires: usize) -> io::Result<Calibrator> {
+        let load = |file: &Option<String>, n| match file {
+            Some(filename) => ChannelMap::from_file(filename, n),
+            None           => Ok(ChannelMap::ideal(n)),
+        };
+        Ok(Calibrator{ sipms      : load(&self.sipm_map, n_sipms)?
+                     , wires      : load(&self.wire_map, n_wires)?
+                     , dark_window: self.dark_window
+                     })
+    }
+}
+
+/// Response of a single channel. `pde` scales the photon detection
+/// efficiency (at most 1), `dark_rate` is in Hz. Only `alive` and `gain`
+/// apply to wires, see `WireReadout`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct ChannelCalib {
+    pub id       : usize,
+    #[serde(default = "yes")]
+    pub alive    : bool,
+    #[serde(default = "one")]
+    pub gain     : f64,
+    #[serde(default = "one")]
+    pub pde      : f64,
+    #[serde(default)]
+    pub dark_rate: f64,
+}
+
+fn yes() -> bool { true }
+fn one() -> f64  { 1.0  }
+
+impl ChannelCalib {
+    pub fn ideal(id: usize) -> Self {
+        Self{ id, alive: true, gain: 1.0, pde: 1.0, dark_rate: 0.0 }
+    }
+
+    /// Detected signal for `n` incoming counts.
+    pub fn respond(&self, n: usize, dark_window: f64) -> usize {
+        self.read_out(self.detect(n), dark_window)
+    }
+
+    /// Photons detected out of `n` incoming ones.
+    pub fn detect(&self, n: usize) -> usize {
+        if !self.alive    { return 0 }
+        if self.pde < 1.0 { binomial(n as u64, self.pde) as usize } else { n }
+    }
+
+    /// Signal for `n` detected photons, with dark counts and gain.
+    pub fn read_out(&self, n: usize, dark_window: f64) -> usize {
+        if !self.alive { return 0 }
+        let dark = self.dark_rate * dark_window * 1e-9;
+        let dark = if dark > 0.0 { poisson(dark) } else { 0.0 };
+        ((n as f64 + dark) * self.gain).round() as usize
+    }
+}
+
+#[derive(Deserialize)]
+struct ChannelMapToml {
+    channels: Vec<ChannelCalib>,
+}
+
+/// Calibration of every channel, indexed by channel id. Channels missing from
+/// the map are ideal.
+#[derive(Debug, Clone)]
+pub struct ChannelMap(Vec<ChannelCalib>);
+
+impl ChannelMap {
+    pub fn ideal(n: usize) -> Self {
+        Self((0..n).map(ChannelCalib::ideal).collect())
+    }
+
+    pub fn new(entries: &[ChannelCalib], n: usize) -> io::Result<Self> {
+        let mut map = Self::ideal(n);
+        for entry in entries {
+            if entry.id >= n {
+                let msg = format!("channel {} out of range, there are {} channels", entry.id, n);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+            if !(0.0..=1.0).contains(&entry.pde) {
+                let msg = format!("channel {}: pde scale must be within [0, 1], found {}", entry.id, entry.pde);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+            if !(0.0..).contains(&entry.gain) {
+                let msg = format!("channel {}: gain must not be negative, found {}", entry.id, entry.gain);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+            if !(0.0..).contains(&entry.dark_rate) {
+                let msg = format!("channel {}: dark rate must not be negative, found {}", entry.id, entry.dark_rate);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+            map.0[entry.id] = *entry;
+        }
+        Ok(map)
+    }
+
+    /// Reads a TOML file (`[[channels]]` tables) or a CSV file with a header
+    /// naming the columns: id and any of alive, gain, pde, dark_rate.
+    pub fn from_file(filename: &str, n: usize) -> io::Result<Self> {
+        let contents = read_to_string(filename)?;
+        let invalid  = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}: {msg}"));
+        let entries  = if filename.ends_with(".toml") {
+            toml::from_str::<ChannelMapToml>(&contents).map_err(|e| invalid(e.to_string()))?.channels
+        } else {
+            parse_csv(&contents).map_err(invalid)?
+        };
+        Self::new(&entries, n).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn get(&self, channel: usize) -> &ChannelCalib {
+        &self.0[channel]
+    }
+}
+
+fn parse_csv(contents: &str) -> Result<Vec<ChannelCalib>, String> {
+    let mut lines = contents.lines()
+                            .map   (str::trim)
+                            .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    let header : Vec<&str> = match lines.next() {
+        Some(h) => h.split(',').map(str::trim).collect(),
+        None    => return Ok(vec![]),
+    };
+    if !header.contains(&"id") { return Err("missing id column".to_owned()) }
+
+    lines.map(|line| {
+        let mut entry = ChannelCalib::ideal(0);
+        let values : Vec<&str> = line.split(',').map(str::trim).collect();
+        if values.len() != header.len() {
+            return Err(format!("expected {} columns in `{line}`", header.len()))
+        }
+        for (name, value) in header.iter().zip(values) {
+            let bad = |e: &dyn ToString| format!("{name} = `{value}`: {}", e.to_string());
+            match *name {
+                "id"        => entry.id        = value.parse().map_err(|e| bad(&e))?,
+                "alive"     => entry.alive     = parse_bool(value).ok_or_else(|| bad(&"expected a boolean"))?,
+                "gain"      => entry.gain      = value.parse().map_err(|e| bad(&e))?,
+                "pde"       => entry.pde       = value.parse().map_err(|e| bad(&e))?,
+                "dark_rate" => entry.dark_rate = value.parse().map_err(|e| bad(&e))?,
+                other       => return Err(format!("unknown column {other}")),
+            }
+        }
+        Ok(entry)
+    }).collect()
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "1" | "true"  | "yes" => Some(true),
+        "0" | "false" | "no"  => Some(false),
+        _                     => None,
+    }
+}
+
+/// Loaded calibration, see `Calibration::load`.
+#[derive(Debug, Clone)]
+pub struct Calibrator {
+    pub sipms      : ChannelMap,
+    pub wires      : ChannelMap,
+    pub dark_window: f64,
+}
+
+impl Calibrator {
+    /// Applies the SiPM map to an image. Channels are numbered row-major.
+    pub fn apply_sipms(&self, img: &DMatrix<usize>) -> DMatrix<usize> {
+        self.read_out_sipms(&self.detect_sipms(img))
+    }
+
+    /// Photon detection only, see `ChannelCalib::detect`.
+    pub fn detect_sipms(&self, img: &DMatrix<usize>) -> DMatrix<usize> {
+        let cols = img.ncols();
+        DMatrix::from_fn(img.nrows(), cols, |r, c| self.sipms.get(r * cols + c).detect(img[(r, c)]))
+    }
+
+    /// Dark counts and gain only, see `ChannelCalib::read_out`.
+    pub fn read_out_sipms(&self, img: &DMatrix<usize>) -> DMatrix<usize> {
+        let cols = img.ncols();
+        DMatrix::from_fn(img.nrows(), cols, |r, c| {
+            self.sipms.get(r * cols + c).read_out(img[(r, c)], self.dark_window)
+        })
+    }
+
+    /// Whether a single photon reaching SiPM `channel` is detected, see
+    /// `ChannelCalib::detect`. Drawn once per photon, so that the image and
+    /// the waveforms see the same photons.
+    pub fn detect_photon(&self, channel: usize) -> bool {
+        self.sipms.get(channel).detect(1) == 1
+    }
+
+    /// Applies the gain of each SiPM to its waveform, zero for dead ones.
+    pub fn read_out_waveforms(&self, mut wfs: DMatrix<f64>) -> DMatrix<f64> {
+        for (ch, mut wf) in wfs.row_iter_mut().enumerate() {
+            let calib = self.sipms.get(ch);
+            wf *= if calib.alive { calib.gain } else { 0.0 };
+        }
+        wfs
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use pretty_assertions::assert_eq;
+    use tempfile::Builder;
+
+    fn write_temp(suffix: &str, contents: &str) -> tempfile::NamedTempFile {
+        let mut file = Builder::new().suffix(suffix).tempfile().unwrap();
+        write!(file, "{contents}").unwrap();
+        file
+    }
+
+    #[test]
+    fn csv_map() {
+        let file = write_temp(".csv", "id, alive, gain\n# comment\n1, false, 1.0\n2, true, 2.5\n");
+        let map  = ChannelMap::from_file(file.path().to_str().unwrap(), 4).unwrap();
+        assert_eq!(*map.get(0), ChannelCalib::ideal(0));
+        assert!(!map.get(1).alive);
+        assert_eq!(map.get(2).gain, 2.5);
+        assert_eq!(map.get(2).pde , 1.0);
+    }
+
+    #[test]
+    fn toml_map() {
+        let file = write_temp(".toml", "[[channels]]\nid = 3\npde = 0.5\ndark_rate = 1e5\n");
+        let map  = ChannelMap::from_file(file.path().to_str().unwrap(), 4).unwrap();
+        assert_eq!(map.get(3).pde      , 0.5);
+        assert_eq!(map.get(3).dark_rate, 1e5);
+        assert!(map.get(3).alive);
+    }
+
+    #[test]
+    fn bad_maps() {
+        let out_of_range = write_temp(".csv" , "id,gain\n9,1.0\n");
+        let bad_pde      = write_temp(".toml", "[[channels]]\nid = 0\npde = 1.5\n");
+        let bad_gain     = write_temp(".csv" , "id,gain\n0,-1.0\n");
+        let bad_dark     = write_temp(".toml", "[[channels]]\nid = 0\ndark_rate = -1e3\n");
+        let bad_column   = write_temp(".csv" , "id,colour\n0,red\n");
+        let no_id        = write_temp(".csv" , "gain\n1.0\n");
+        for file in [out_of_range, bad_pde, bad_gain, bad_dark, bad_column, no_id] {
+            assert!(ChannelMap::from_file(file.path().to_str().unwrap(), 4).is_err());
+        }
+    }
+
+    #[test]
+    fn apply_sipms() {
+        let entries = [ ChannelCalib{alive: false, ..ChannelCalib::ideal(0)}
+                      , ChannelCalib{gain : 2.0  , ..ChannelCalib::ideal(3)}
+                      , ChannelCalib{pde  : 0.0  , ..ChannelCalib::ideal(4)}
+                      ];
+        let calib = Calibrator{ sipms: ChannelMap::new(&entries, 6).unwrap()
+                              , wires: ChannelMap::ideal(0)
+                              , dark_window: 0.0 };
+        let img = DMatrix::from_row_slice(2, 3, &[10, 20, 30, 40, 50, 60]);
+        let out = calib.apply_sipms(&img);
+        assert_eq!(out, DMatrix::from_row_slice(2, 3, &[0, 20, 30, 80, 0, 60]));
+    }
+
+    #[test]
+    fn dark_counts() {
+        let entries = [ChannelCalib{dark_rate: 1e7, ..ChannelCalib::ideal(0)}];
+        let calib   = Calibrator{ sipms: ChannelMap::new(&entries, 1).unwrap()
+                                , wires: ChannelMap::ideal(0)
+                                , dark_window: 1000.0 };
+        let img   = DMatrix::from_element(1, 1, 0);
+        let total : usize = (0..1000).map(|_| calib.apply_sipms(&img)[(0, 0)]).sum();
+        let mean  = total as f64 / 1000.0;
+        assert!((mean - 10.0).abs() < 1.0, "mean {mean}");
+    }
+}
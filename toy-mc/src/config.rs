@@ -1,24 +1,345 @@
+use std::{fs, io};
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
-use config::{Config, ConfigError, File};
+use schemars::JsonSchema;
+use config::{Config, ConfigBuilder, ConfigError, Environment, File, FileFormat};
+use config::builder::DefaultState;
+use toml_edit::DocumentMut;
 
-use crate::{Geometry, SimParams};
+use crate::{Geometry, SimParams, Timing, Detailed, Calibration, WireReadout, Sweep, Scalar, Gas};
+use crate::units::{Dimension, Quantity};
+use crate::migrate::{migrate, CONFIG_VERSION};
+use crate::random::MAX_SEED;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Built-in configurations, selected with `--preset` or included as
+/// `preset:<name>`.
+const PRESETS: [(&str, &str); 2] = [
+    ("default", include_str!("../conf/test.toml")),
+    ("timing" , include_str!("../conf/presets/timing.toml")),
+];
+
+/// Entries holding paths, which are relative to the file that gives them.
+const PATH_ENTRIES: [&str; 3] = [ "geometry.sipm_plane.layout_file"
+                                , "calibration.sipm_map"
+                                , "calibration.wire_map"
+                                ];
+
+/// Configuration file or built-in preset, read on top of the layers it
+/// lists in `include`.
+enum Layer {
+    File  (PathBuf),
+    Preset(&'static str, &'static str), // name, contents
+}
+
+impl Layer {
+    /// `preset:<name>`, or a path relative to `dir`.
+    fn parse(spec: &str, dir: &Path) -> Result<Self, ConfigError> {
+        let Some(name) = spec.strip_prefix("preset:") else { return Ok(Layer::File(dir.join(spec))) };
+        PRESETS.iter()
+               .find(|(n, _)| *n == name)
+               .map(|&(n, contents)| Layer::Preset(n, contents))
+               .ok_or_else(|| ConfigError::Message(format!("unknown preset {name}, expected one of {}", SimConfig::presets().join(", "))))
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Layer::File(path)      => path.canonicalize().unwrap_or(path.clone()).display().to_string(),
+            Layer::Preset(name, _) => format!("preset:{name}"),
+        }
+    }
+
+    /// Directory of the relative paths in `include`.
+    fn dir(&self) -> PathBuf {
+        match self {
+            Layer::File(path)   => path.parent().map_or(PathBuf::new(), Path::to_owned),
+            Layer::Preset(_, _) => PathBuf::new(),
+        }
+    }
+
+    /// Contents of a TOML layer, which can be upgraded. Other formats are
+    /// read by `config` as they are.
+    fn document(&self) -> Result<Option<DocumentMut>, ConfigError> {
+        let contents = match self {
+            Layer::Preset(_, contents)         => contents.to_string(),
+            Layer::File(path) if is_toml(path) => fs::read_to_string(path)
+                                                    .map_err(|e| ConfigError::Message(format!("{}: {e}", path.display())))?,
+            Layer::File(_)                     => return Ok(None),
+        };
+        contents.parse()
+                .map(Some)
+                .map_err(|e| ConfigError::Message(format!("{}: {e}", self.name())))
+    }
+
+    fn includes(&self, doc: Option<&DocumentMut>) -> Result<Vec<String>, ConfigError> {
+        let source = match doc {
+            Some(doc) => Config::builder().add_source(File::from_str(&doc.to_string(), FileFormat::Toml)),
+            None      => Config::builder().add_source(SimConfig::file(&self.path_name())),
+        };
+        match source.build()?.get("include") {
+            Err(ConfigError::NotFound(_)) => Ok(vec![]),
+            other                         => other,
+        }
+    }
+
+    fn path_name(&self) -> String {
+        match self {
+            Layer::File(path)   => path.to_string_lossy().into_owned(),
+            Layer::Preset(_, _) => self.name(),
+        }
+    }
+
+    /// Reads the layers included by this one, recursively, and then this one
+    /// into `layers`, so that each layer overrides those it includes. `stack`
+    /// holds the layers being included, to catch cycles.
+    fn with_includes(self, stack: &mut Vec<String>, layers: &mut Vec<(Layer, Option<DocumentMut>)>) -> Result<(), ConfigError> {
+        let name = self.name();
+        if stack.contains(&name) {
+            return Err(ConfigError::Message(format!("{name} includes itself")))
+        }
+        let doc = self.document()?;
+        stack.push(name);
+        for spec in self.includes(doc.as_ref())? {
+            Layer::parse(&spec, &self.dir())?.with_includes(stack, layers)?;
+        }
+        stack.pop();
+        layers.push((self, doc));
+        Ok(())
+    }
+
+    /// Adds this layer, upgraded to the current version if it is TOML. A
+    /// layer without `version` is taken as `version`. The changes made by
+    /// the upgrade are added to `notices`.
+    fn add_to(&self, builder: ConfigBuilder<DefaultState>, doc: Option<DocumentMut>, version: i64, notices: &mut Vec<String>) -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        let Some(mut doc) = doc else { return Ok(builder.add_source(SimConfig::file(&self.path_name()))) };
+        let changes = migrate(&mut doc, version).map_err(|e| ConfigError::Message(format!("{}: {e}", self.name())))?;
+        notices.extend(changes.into_iter().map(|n| format!("{}: {n}", self.name())));
+        self.resolve_paths(&mut doc);
+        Ok(builder.add_source(File::from_str(&doc.to_string(), FileFormat::Toml)))
+    }
+
+    /// Makes the relative paths given by this layer absolute, from its
+    /// directory rather than the working directory, so that they also hold
+    /// in the `run.conf` written next to the output.
+    fn resolve_paths(&self, doc: &mut DocumentMut) {
+        for key in PATH_ENTRIES {
+            let Some(item) = key.split('.').try_fold(doc.as_item_mut(), |item, k| item.as_table_like_mut()?.get_mut(k)) else { continue };
+            let Some(path) = item.as_str().map(|p| self.dir().join(p)) else { continue };
+            if let Ok(path) = std::path::absolute(path) {
+                *item = toml_edit::value(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+}
+
+/// Version of the layers read together, the latest any of them gives.
+/// Layers without `version`, such as fragments including a full
+/// configuration, share it. Version 1 did not have the entry.
+fn common_version(layers: &[(Layer, Option<DocumentMut>)]) -> i64 {
+    layers.iter()
+          .filter_map(|(_, doc)| doc.as_ref()?.get("version")?.as_integer())
+          .max()
+          .unwrap_or(1)
+}
+
+/// Whether `path` is read as TOML, and so can be upgraded.
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml" || ext == "conf")
+}
+
+fn current_version() -> i64 {
+    CONFIG_VERSION
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct SimConfig {
-    pub geometry  : Geometry,
-    pub sim_params: SimParams,
-    pub n_events  : usize,
-    pub output    : String,
+    /// Layout of the file; older files are upgraded when read
+    #[serde(default = "current_version")]
+    pub version    : i64,
+    /// Wires, sensors and gaps of the detector
+    pub geometry   : Geometry,
+    /// Energy deposited and its conversion to electrons and light
+    pub sim_params : SimParams,
+    /// Gas filling the detector, deriving the `sim_params` not given
+    #[serde(default)]
+    pub gas        : Option<Gas>,
+    /// Time response, enables the waveform output
+    #[serde(default)]
+    pub timing     : Option<Timing>,
+    /// Fine image written along with each event
+    #[serde(default)]
+    pub detailed   : Option<Detailed>,
+    /// Per-channel calibration maps
+    #[serde(default)]
+    pub calibration: Option<Calibration>,
+    /// Wire electronics, producing digitized wire signals
+    #[serde(default)]
+    pub wire_readout: Option<WireReadout>,
+    /// Seed of the random generator, drawn at startup if not given
+    #[serde(default)]
+    pub seed       : Option<u64>,
+    /// Runs differing in a few entries, one per point
+    #[serde(default)]
+    pub sweep      : Option<Sweep>,
+    /// Number of events per run
+    pub n_events   : usize,
+    /// Output directory
+    pub output     : String,
+    /// Changes made while upgrading older files, to be reported
+    #[serde(skip)]
+    pub warnings   : Vec<String>,
 }
 
 impl SimConfig {
+    /// Reads a configuration file, or a built-in preset as `preset:<name>`.
+    /// `.conf` files, as written next to the output, are TOML. Files may
+    /// list others in `include`, paths relative to their own, which they
+    /// override table by table. TOML files of older versions are upgraded,
+    /// listing the changes in `warnings`.
     pub fn new(filename: &str) -> Result<Self, ConfigError> {
-        let s = Config::builder()
-            .add_source(File::with_name(filename))
-            .build()?;
+        let mut warnings = vec![];
+        let conf = Self::build(Self::layers(&[filename.to_owned()], &mut warnings)?, &[])?;
+        Ok(Self{warnings, ..conf})
+    }
+
+    /// Reads configuration files or presets, each overriding the previous
+    /// ones, then the `TOYMC_` environment variables and then `key=value`
+    /// assignments. Keys are dotted paths, `geometry.wire_plane.wire_pitch=4.0`,
+    /// or, in variable names, separated by `__`,
+    /// `TOYMC_GEOMETRY__WIRE_PLANE__WIRE_PITCH=4.0`.
+    pub fn load(sources: &[String], assignments: &[String]) -> Result<Self, ConfigError> {
+        Self::load_with(sources, Self::environment(), assignments)
+    }
+
+    fn load_with(sources: &[String], env: Environment, assignments: &[String]) -> Result<Self, ConfigError> {
+        let values = assignments.iter()
+                                .map(|a| a.split_once('=')
+                                          .map(|(k, v)| (k.trim().to_owned(), Scalar::Text(v.trim().to_owned())))
+                                          .ok_or_else(|| ConfigError::Message(format!("expected key=value, found {a}"))))
+                                .collect::<Result<Vec<_>, _>>()?;
+        let mut warnings = vec![];
+        let conf = Self::build(Self::layers(sources, &mut warnings)?.add_source(env), &values)?;
+        Ok(Self{warnings, ..conf})
+    }
+
+    /// Rewrites a TOML configuration file in the current version, keeping
+    /// its comments, and returns the changes made.
+    pub fn migrate_file(filename: &str) -> io::Result<Vec<String>> {
+        let invalid    = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut layers = vec![];
+        Layer::File(filename.into()).with_includes(&mut vec![], &mut layers).map_err(|e| invalid(e.to_string()))?;
+        let version    = common_version(&layers);
+        let Some((_, Some(mut doc))) = layers.pop() else { return Err(invalid(format!("{filename}: not a TOML file"))) };
+        let before  = doc.to_string();
+        let notices = migrate(&mut doc, version).map_err(invalid)?;
+        if doc.to_string() != before {
+            fs::write(filename, doc.to_string())?;
+        }
+        Ok(notices)
+    }
+
+    /// The default configuration, with every section and option described.
+    pub fn default_toml() -> &'static str {
+        PRESETS[0].1
+    }
+
+    /// JSON Schema of the configuration files.
+    pub fn json_schema() -> String {
+        serde_json::to_string_pretty(&schemars::schema_for!(SimConfig)).expect("Could not serialize schema")
+    }
+
+    /// Names of the built-in presets.
+    pub fn presets() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
+    fn layers(sources: &[String], notices: &mut Vec<String>) -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        let mut layers = vec![];
+        for spec in sources {
+            Layer::parse(spec, Path::new(""))?.with_includes(&mut vec![], &mut layers)?;
+        }
+        let version = common_version(&layers);
+        layers.into_iter().try_fold(Config::builder(), |builder, (layer, doc)| layer.add_to(builder, doc, version, notices))
+    }
 
-        // You can deserialize (and thus freeze) the entire configuration as
-        s.try_deserialize()
+    fn file(filename: &str) -> File<config::FileSourceFile, FileFormat> {
+        if filename.ends_with(".conf") { File::new(filename, FileFormat::Toml) }
+        else                           { File::with_name(filename)             }
+    }
+
+    fn environment() -> Environment {
+        Environment::with_prefix("TOYMC")
+            .prefix_separator("_")
+            .separator("__")
+            .try_parsing(true)
+    }
+
+    /// Applies `values` on top of the sources of `builder`, and the entries
+    /// derived from the gas below them. Every key must name an entry of the
+    /// resulting configuration, to catch typos.
+    fn build(builder: ConfigBuilder<DefaultState>, values: &[(String, Scalar)]) -> Result<Self, ConfigError> {
+        let mut builder = builder;
+        for (key, value) in values {
+            builder = builder.set_override(key, value.clone())?;
+        }
+        let builder = Self::add_gas_defaults(builder)?;
+        let conf : Self = builder.build()?.try_deserialize()?;
+        if conf.version != CONFIG_VERSION {
+            return Err(ConfigError::Message(format!("unsupported version {}, expected {CONFIG_VERSION}", conf.version)))
+        }
+        if conf.seed.is_some_and(|seed| seed > MAX_SEED) {
+            return Err(ConfigError::Message(format!("seed above the largest of {MAX_SEED}")))
+        }
+        if let Some(timing) = &conf.timing {
+            timing.validate().map_err(ConfigError::Message)?;
+        }
+        if let Some(readout) = &conf.wire_readout {
+            readout.validate().map_err(ConfigError::Message)?;
+        }
+        match values.iter().find(|(key, _)| !conf.has_entry(key)) {
+            Some((key, _)) => Err(ConfigError::NotFound(key.clone())),
+            None           => Ok(conf),
+        }
+    }
+
+    /// Sets the entries derived from the `[gas]` section, if any, as
+    /// defaults, so that any explicit value takes precedence.
+    fn add_gas_defaults(builder: ConfigBuilder<DefaultState>) -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        let config = builder.build_cloned()?;
+        let gas : Gas = match config.get("gas") {
+            Err(ConfigError::NotFound(_)) => return Ok(builder),
+            other                         => other?,
+        };
+        let wire_r     = config.get::<Quantity>("geometry.wire_plane.wire_r")?
+                               .value(Dimension::Length)
+                               .map_err(ConfigError::Message)?;
+        let drift_time = config.get::<f64>("timing.drift_time").ok();
+        if gas.el_range(wire_r).is_some_and(|range| range <= 0.0) {
+            return Err(ConfigError::Message(format!("gas.el_field below EL threshold of {:.1} kV/cm", gas.el_threshold())))
+        }
+        gas.derived(wire_r, drift_time)
+           .into_iter()
+           .try_fold(builder, |builder, (key, value)| builder.set_default(key, value))
+    }
+
+    /// Whether the dotted path `key` names an entry of the configuration.
+    fn has_entry(&self, key: &str) -> bool {
+        let Ok(value) = toml::Value::try_from(self) else { return false };
+        key.split('.').try_fold(&value, |v, k| v.get(k)).is_some()
+    }
+
+    /// The same configuration with the entries at the dotted paths in
+    /// `values` replaced. Entries still holding the value derived from the
+    /// gas are derived again, so that they follow changes to the gas.
+    pub fn with_values(&self, values: &[(String, Scalar)]) -> Result<Self, ConfigError> {
+        let mut table = toml::Table::try_from(self).map_err(|e| ConfigError::Message(e.to_string()))?;
+        let derived   = self.gas.iter().flat_map(|gas| gas.derived(self.geometry.wire_plane.wire_r, self.timing.as_ref().map(|t| t.drift_time)));
+        for (key, value) in derived {
+            let Some((section, name)) = key.split_once('.')                                       else { continue };
+            let Some(section)         = table.get_mut(section).and_then(toml::Value::as_table_mut) else { continue };
+            if section.get(name).and_then(toml::Value::as_float) == Some(value) { section.remove(name); }
+        }
+        let contents = table.to_string();
+        Self::build(Config::builder().add_source(File::from_str(&contents, FileFormat::Toml)), values)
     }
 
     pub fn override_n_events(self, n_events: usize) -> Self {
@@ -29,16 +350,397 @@ impl SimConfig {
         Self{output, ..self}
     }
 
+    /// Enables the fine image, using the default binning if the configuration
+    /// does not provide one.
+    pub fn enable_detailed(self) -> io::Result<Self> {
+        let detailed = match self.detailed.clone() {
+            Some(d) => d,
+            None    => Detailed::covering(&self.geometry.sipm_plane.layout()?),
+        };
+        Ok(Self{detailed: Some(detailed), ..self})
+    }
+
+    /// Wire electronics, if the wires are digitized. A wire calibration map
+    /// without a `[wire_readout]` section uses the ideal readout.
+    pub fn wire_readout(&self) -> Option<WireReadout> {
+        let has_wire_map = self.calibration.as_ref().is_some_and(|c| c.wire_map.is_some());
+        match &self.wire_readout {
+            Some(r)               => Some(r.clone()),
+            None if has_wire_map  => Some(WireReadout::default()),
+            None                  => None,
+        }
+    }
+
     pub fn overrides(self, n_events: Option<usize>, output: Option<String>) -> Self {
         let conf = self;
         let conf = match n_events {
             Some(n) => conf.override_n_events(n),
             None    => conf,
         };
-        let conf = match output {
+        match output {
             Some(p) => conf.override_output(p),
             None    => conf,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use tempfile::Builder;
+    use crate::SweepMode;
+
+    #[test]
+    fn with_values() {
+        let conf   = SimConfig::new("conf/test.toml").unwrap();
+        let values = [ ("geometry.wire_plane.wire_pitch".to_owned(), Scalar::Float(4.0))
+                     , ("geometry.wire_plane.n_wires"   .to_owned(), Scalar::Int(20))
+                     , ("output"                        .to_owned(), Scalar::Text("elsewhere/".to_owned()))
+                     ];
+        let new    = conf.with_values(&values).unwrap();
+        assert_eq!(new.geometry.wire_plane.wire_pitch, 4.0);
+        assert_eq!(new.geometry.wire_plane.n_wires   , 20);
+        assert_eq!(new.output                        , "elsewhere/");
+        assert_eq!(new.sim_params.light_yield        , conf.sim_params.light_yield);
+        assert!(conf.with_values(&[("n_events".to_owned(), Scalar::Text("many".to_owned()))]).is_err());
+    }
+
+    #[test]
+    fn units() {
+        let test = ["conf/test.toml".to_owned()];
+        let env  = || SimConfig::environment().source(Some(Default::default()));
+        let sets = [ "geometry.wire_plane.wire_r=5 um"
+                   , "geometry.wire_plane.wire_rotation=0.5 rad"
+                   , "sim_params.dep_energy=41.5 keV"
+                   ].map(String::from);
+        let conf = SimConfig::load_with(&test, env(), &sets).unwrap();
+        assert_eq!(conf.geometry.wire_plane.wire_r, 5e-3);
+        assert_eq!(conf.sim_params.dep_energy     , 41500.0);
+        assert!((conf.geometry.wire_plane.wire_rotation - 0.5f64.to_degrees()).abs() < 1e-12);
+
+        // written back in canonical units
+        let written = toml::to_string(&conf).unwrap();
+        assert!(written.contains("dep_energy = 41500.0"), "{written}");
+
+        let err = SimConfig::load_with(&test, env(), &["geometry.buffer=3 keV".to_owned()]).unwrap_err();
+        assert!(err.to_string().contains("expected a length"), "{err}");
+    }
+
+    #[test]
+    fn invalid_wire_readout() {
+        let test = ["conf/test.toml".to_owned()];
+        let env  = || SimConfig::environment().source(Some(Default::default()));
+        let sets = ["wire_readout.electrons_per_adc=4".to_owned()];
+        assert!(SimConfig::load_with(&test, env(), &sets).unwrap().wire_readout.is_some());
+        let err  = SimConfig::load_with(&test, env(), &["wire_readout.electrons_per_adc=0".to_owned()]).unwrap_err();
+        assert!(err.to_string().contains("must be positive"), "{err}");
+
+        let dir  = tempfile::tempdir().unwrap();
+        let base = Path::new("conf/test.toml").canonicalize().unwrap();
+        let file = dir.path().join("induction.toml");
+        std::fs::write(&file, format!("include = [{:?}]\n[wire_readout]\ninduction = [0.3, 0.3]\n", base.display().to_string())).unwrap();
+        let err  = SimConfig::new(file.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("add up to 0.6"), "{err}");
+    }
+
+    #[test]
+    fn invalid_timing() {
+        let test = ["preset:timing".to_owned()];
+        let env  = || SimConfig::environment().source(Some(Default::default()));
+        for set in ["timing.bin_width=0", "timing.bin_width=-25", "timing.n_samples=0"] {
+            let err = SimConfig::load_with(&test, env(), &[set.to_owned()]).unwrap_err();
+            assert!(err.to_string().contains("must be"), "{err}");
+        }
+    }
+
+    #[test]
+    fn unknown_entry() {
+        let conf = SimConfig::new("conf/test.toml").unwrap();
+        assert!(conf.with_values(&[("sim_params.light_yeld".to_owned(), Scalar::Float(50.0))]).is_err());
+        assert!(conf.with_values(&[("geometry".to_owned(), Scalar::Float(50.0))]).is_err());
+    }
+
+    #[test]
+    fn assignments_and_environment() {
+        let env  = |vars: &[(&str, &str)]| {
+            let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            SimConfig::environment().source(Some(vars))
         };
-        conf
+        let sets = ["sim_params.light_yield=50".to_owned(), "seed = 12".to_owned()];
+        let test = ["conf/test.toml".to_owned()];
+        let conf = SimConfig::load_with(&test, env(&[("TOYMC_GEOMETRY__BUFFER", "7.5"), ("TOYMC_N_EVENTS", "3"), ("OTHER", "1")]), &sets).unwrap();
+        assert_eq!(conf.geometry.buffer        , 7.5);
+        assert_eq!(conf.n_events               , 3);
+        assert_eq!(conf.sim_params.light_yield , 50.0);
+        assert_eq!(conf.seed                   , Some(12));
+
+        // assignments take precedence over the environment
+        let conf = SimConfig::load_with(&test, env(&[("TOYMC_SIM_PARAMS__LIGHT_YIELD", "20")]), &sets).unwrap();
+        assert_eq!(conf.sim_params.light_yield , 50.0);
+
+        assert!(SimConfig::load_with(&test, env(&[]), &["seed".to_owned()]).is_err());
+        assert!(SimConfig::load_with(&test, env(&[]), &["n_events=many".to_owned()]).is_err());
+        for seed in ["9223372036854775808", "18446744073709551615"] {
+            assert!(SimConfig::load_with(&test, env(&[("TOYMC_SEED", seed)]), &[]).is_err(), "{seed}");
+            assert!(SimConfig::load_with(&test, env(&[]), &[format!("seed={seed}")]).is_err(), "{seed}");
+        }
+    }
+
+    #[test]
+    fn includes_deep_merge() {
+        let dir   = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| std::fs::write(dir.path().join(name), contents).unwrap();
+        write("base.toml"   , &std::fs::read_to_string("conf/test.toml").unwrap());
+        write("variant.toml", "include = [\"base.toml\"]\nn_events = 7\n[geometry.wire_plane]\nwire_pitch = 4.0\n");
+        write("nested.toml" , "include = [\"variant.toml\"]\n[sim_params]\nlight_yield = 50\n");
+
+        let base = SimConfig::new("conf/test.toml").unwrap();
+        let conf = SimConfig::new(dir.path().join("nested.toml").to_str().unwrap()).unwrap();
+        assert_eq!(conf.n_events                     , 7);
+        assert_eq!(conf.geometry.wire_plane.wire_pitch, 4.0);
+        assert_eq!(conf.geometry.wire_plane.n_wires   , base.geometry.wire_plane.n_wires);
+        assert_eq!(conf.sim_params.light_yield        , 50.0);
+        assert_eq!(conf.sim_params.w_i                , base.sim_params.w_i);
+
+        write("loop_a.toml", "include = [\"loop_b.toml\"]\n");
+        write("loop_b.toml", "include = [\"loop_a.toml\"]\n");
+        let err = SimConfig::new(dir.path().join("loop_a.toml").to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("includes itself"), "{err}");
+    }
+
+    #[test]
+    fn presets() {
+        let default = SimConfig::new("preset:default").unwrap();
+        let timing  = SimConfig::new("preset:timing" ).unwrap();
+        assert!(default.timing.is_none());
+        assert!(timing .timing.is_some());
+        assert_eq!(toml::to_string(&timing.geometry).unwrap(), toml::to_string(&default.geometry).unwrap());
+        assert!(SimConfig::new("preset:unknown").is_err());
+
+        let sources = ["preset:default".to_owned(), "preset:timing".to_owned()];
+        let env     = SimConfig::environment().source(Some(Default::default()));
+        assert!(SimConfig::load_with(&sources, env, &[]).unwrap().timing.is_some());
+    }
+
+    /// The default configuration with its commented-out entries and
+    /// sections enabled.
+    fn uncommented_default() -> toml::Value {
+        let contents = SimConfig::default_toml().lines().map(|line| {
+            let entry = line.trim_start().trim_start_matches('#').trim_start();
+            let key   = entry.split('=').next().unwrap().trim();
+            let is_entry = entry.starts_with('[')
+                        || (entry.contains('=') && !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            if is_entry { entry } else { line }
+        }).collect::<Vec<_>>().join("\n");
+        toml::from_str(&contents).unwrap()
+    }
+
+    /// Object schema behind references, optional values and arrays.
+    fn resolve<'a>(defs: &'a serde_json::Value, schema: &'a serde_json::Value) -> &'a serde_json::Value {
+        if let Some(name) = schema["$ref"].as_str() {
+            return resolve(defs, &defs[name.trim_start_matches("#/$defs/")])
+        }
+        if let Some(items) = schema.get("items") {
+            return resolve(defs, items)
+        }
+        match schema["anyOf"].as_array().and_then(|a| a.iter().find(|s| s["type"] != "null")) {
+            Some(variant) => resolve(defs, variant),
+            None          => schema,
+        }
+    }
+
+    /// Properties of `schema`, recursively, missing from `value` or without
+    /// a description. Arrays of tables need each property in one of them.
+    fn undocumented(defs: &serde_json::Value, schema: &serde_json::Value, value: &toml::Value, path: &str, missing: &mut Vec<String>) {
+        let Some(properties) = resolve(defs, schema)["properties"].as_object() else { return };
+        for (key, property) in properties {
+            let path   = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+            let values = match value {
+                toml::Value::Array(tables) => tables.iter().filter_map(|t| t.get(key)).collect(),
+                other                      => other.get(key).into_iter().collect::<Vec<_>>(),
+            };
+            if property.get("description").is_none() { missing.push(format!("{path} has no description")) }
+            if values.is_empty()                     { missing.push(format!("{path} is not in the default")) }
+            values.into_iter().for_each(|v| undocumented(defs, property, v, &path, missing));
+        }
+    }
+
+    #[test]
+    fn schema_covers_default() {
+        let schema : serde_json::Value = serde_json::from_str(&SimConfig::json_schema()).unwrap();
+        let default: toml::Table       = toml::from_str(SimConfig::default_toml()).unwrap();
+        for key in default.keys() {
+            assert!(schema["properties"].get(key).is_some(), "{key} missing from the schema");
+        }
+        let pitch = &schema["$defs"]["WirePlane"]["properties"]["wire_pitch"];
+        assert_eq!(pitch["$ref"], "#/$defs/Quantity");
+        let types = schema["$defs"]["Quantity"].to_string();
+        assert!(types.contains("number") && types.contains("string"), "{types}");
+    }
+
+    #[test]
+    fn default_documents_every_option() {
+        let schema  : serde_json::Value = serde_json::from_str(&SimConfig::json_schema()).unwrap();
+        let mut missing = vec![];
+        undocumented(&schema["$defs"], &schema, &uncommented_default(), "", &mut missing);
+        assert!(missing.is_empty(), "{missing:#?}");
+    }
+
+    #[test]
+    fn sweep_section() {
+        let mut file = Builder::new().suffix(".toml").tempfile().unwrap();
+        let contents = std::fs::read_to_string("conf/test.toml").unwrap() + r#"
+[sweep]
+mode = "zip"
+
+  [[sweep.parameter]]
+  key    = "geometry.buffer"
+  values = [4.0, 6]
+
+  [[sweep.parameter]]
+  key   = "sim_params.light_yield"
+  range = { start = 10, stop = 20, n = 2 }
+"#;
+        file.write_all(contents.as_bytes()).unwrap();
+        let conf  = SimConfig::new(file.path().to_str().unwrap()).unwrap();
+        let sweep = conf.sweep.unwrap();
+        assert_eq!(sweep.mode, SweepMode::Zip);
+        assert_eq!(sweep.parameters[0].values, vec![Scalar::Float(4.0), Scalar::Int(6)]);
+        assert_eq!(sweep.parameters[1].values(), vec![Scalar::Float(10.0), Scalar::Float(20.0)]);
+    }
+
+    #[test]
+    fn older_versions_upgraded() {
+        let dir      = tempfile::tempdir().unwrap();
+        let current  = SimConfig::new("conf/test.toml").unwrap();
+        let contents = std::fs::read_to_string("conf/test.toml").unwrap()
+                           .replace("version  = 2", "")
+                           .replace("\"45 deg\"", "0.5");
+        let path     = dir.path().join("old.toml");
+        std::fs::write(&path, &contents).unwrap();
+
+        let conf = SimConfig::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(conf.version, CONFIG_VERSION);
+        assert_eq!(conf.warnings.len(), 1);
+        assert!(conf.warnings[0].contains("wire_rotation"), "{:?}", conf.warnings);
+        assert!((conf.geometry.wire_plane.wire_rotation - 0.5f64.to_degrees()).abs() < 1e-12);
+        assert!(current.warnings.is_empty());
+
+        let changes  = SimConfig::migrate_file(path.to_str().unwrap()).unwrap();
+        let migrated = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(changes, vec![conf.warnings[0].split_once(": ").unwrap().1.to_owned()]);
+        assert!(migrated.contains("wire_rotation = \"0.5 rad\" # angle of the wires"), "{migrated}");
+        assert!(SimConfig::migrate_file(path.to_str().unwrap()).unwrap().is_empty());
+        assert!(SimConfig::new(path.to_str().unwrap()).unwrap().warnings.is_empty());
+
+        std::fs::write(&path, contents.replace("n_events", "version = 3\nn_events")).unwrap();
+        let err = SimConfig::new(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("unsupported version 3"), "{err}");
+    }
+
+    #[test]
+    fn paths_relative_to_the_file() {
+        let dir  = tempfile::tempdir().unwrap();
+        let base = Path::new("conf/test.toml").canonicalize().unwrap();
+        std::fs::create_dir(dir.path().join("detector")).unwrap();
+        std::fs::write(dir.path().join("detector/layout.csv"), "x, y, size_x, size_y\n0, 0, 6, 6\n").unwrap();
+        std::fs::write(dir.path().join("detector/layout.toml"),
+                       format!("include = [{:?}]\n[geometry.sipm_plane]\nlayout_file = \"layout.csv\"\n", base.display().to_string())).unwrap();
+        std::fs::write(dir.path().join("run.toml"), "include = [\"detector/layout.toml\"]\n").unwrap();
+
+        let conf = SimConfig::new(dir.path().join("run.toml").to_str().unwrap()).unwrap();
+        let file = conf.geometry.sipm_plane.layout_file.as_deref().unwrap();
+        assert_eq!(Path::new(file), dir.path().join("detector/layout.csv"));
+        assert_eq!(conf.geometry.sipm_plane.layout().unwrap().n_channels(), 1);
+        // read once
+        std::fs::remove_file(dir.path().join("detector/layout.csv")).unwrap();
+        assert_eq!(conf.geometry.sipm_plane.layout().unwrap().n_channels(), 1);
+    }
+
+    #[test]
+    fn calibration_maps_relative_to_the_file() {
+        let dir  = tempfile::tempdir().unwrap();
+        let base = Path::new("conf/test.toml").canonicalize().unwrap();
+        std::fs::create_dir(dir.path().join("calib")).unwrap();
+        std::fs::write(dir.path().join("calib/sipm_map.csv"), "id, alive\n0, false\n").unwrap();
+        std::fs::write(dir.path().join("calib/wire_map.csv"), "id, gain\n1, 2.0\n").unwrap();
+        std::fs::write(dir.path().join("calib/maps.toml"),
+                       format!("include = [{:?}]\n[calibration]\nsipm_map = \"sipm_map.csv\"\nwire_map = \"wire_map.csv\"\n", base.display().to_string())).unwrap();
+
+        let conf  = SimConfig::new(dir.path().join("calib/maps.toml").to_str().unwrap()).unwrap();
+        let calib = conf.calibration.as_ref().unwrap();
+        assert_eq!(Path::new(calib.sipm_map.as_deref().unwrap()), dir.path().join("calib/sipm_map.csv"));
+        assert_eq!(Path::new(calib.wire_map.as_deref().unwrap()), dir.path().join("calib/wire_map.csv"));
+        let calib = calib.load(4, 2).unwrap();
+        assert!(!calib.sipms.get(0).alive);
+        assert_eq!(calib.wires.get(1).gain, 2.0);
+    }
+
+    #[test]
+    fn unversioned_fragment() {
+        let dir      = tempfile::tempdir().unwrap();
+        let base     = Path::new("conf/test.toml").canonicalize().unwrap();
+        let fragment = dir.path().join("fragment.toml");
+        std::fs::write(&fragment, format!("include = [{:?}]\n\n[geometry.wire_plane]\nwire_rotation = 30\n", base.display().to_string())).unwrap();
+
+        // read, and upgraded, as the version of the file it includes
+        let conf = SimConfig::new(fragment.to_str().unwrap()).unwrap();
+        assert!(conf.warnings.is_empty(), "{:?}", conf.warnings);
+        assert_eq!(conf.geometry.wire_plane.wire_rotation, 30.0);
+        assert!(SimConfig::migrate_file(fragment.to_str().unwrap()).unwrap().is_empty());
+        assert!(SimConfig::new("preset:timing").unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    fn gas_derived_parameters() {
+        let dir      = tempfile::tempdir().unwrap();
+        let path     = dir.path().join("gas.toml");
+        let contents = "include = [\"preset:timing\"]\n\
+                        [sim_params]\nlight_yield = 50\n\
+                        [gas]\nspecies = { Xe = 0.9, Ar = 0.1 }\npressure = \"10 bar\"\nel_field = \"75 kV/cm\"\ndrift_length = \"30 cm\"\n";
+        std::fs::write(&path, contents.replace("light_yield = 50", "w_i = 15.6")).unwrap();
+        let test = SimConfig::new("preset:timing").unwrap();
+        let conf = SimConfig::new(path.to_str().unwrap()).unwrap();
+        let gas  = conf.gas.clone().unwrap();
+        let (trans, long) = gas.diffusion().unwrap();
+        assert_eq!(conf.sim_params.w_i                , 15.6); // explicit
+        assert_eq!(conf.sim_params.fano_factor        , test.sim_params.fano_factor); // explicit in the preset
+        assert_eq!(conf.sim_params.el_range           , test.sim_params.el_range);
+        assert_eq!(conf.sim_params.cloud_r            , test.sim_params.cloud_r);
+
+        // derived when not given anywhere
+        let sources = [path.to_str().unwrap().to_owned()];
+        let env     = || SimConfig::environment().source(Some(Default::default()));
+        let bare    = std::fs::read_to_string("conf/test.toml").unwrap()
+                          .lines()
+                          .filter(|l| !["w_i", "fano_factor", "el_range", "cloud_r", "light_yield"].iter().any(|k| l.starts_with(k)))
+                          .collect::<Vec<_>>()
+                          .join("\n");
+        std::fs::write(dir.path().join("base.toml"), bare).unwrap();
+        std::fs::write(&path, contents.replace("preset:timing", "base.toml").replace("light_yield = 50", "")).unwrap();
+        let conf = SimConfig::load_with(&sources, env(), &[]).unwrap();
+        assert_eq!(conf.sim_params.w_i         , gas.w_i());
+        assert_eq!(conf.sim_params.fano_factor , gas.fano_factor());
+        assert_eq!(conf.sim_params.light_yield , gas.light_yield(conf.geometry.wire_plane.wire_r).unwrap());
+        assert_eq!(conf.sim_params.el_range    , gas.el_range   (conf.geometry.wire_plane.wire_r).unwrap());
+        assert_eq!(conf.sim_params.cloud_r     , 2.0 * trans);
+        assert!(conf.timing.is_none());
+        assert!(long > 0.0);
+
+        // an explicit value still wins, derived values follow the gas
+        let conf = SimConfig::load_with(&sources, env(), &["sim_params.light_yield=12".to_owned()]).unwrap();
+        let high = conf.with_values(&[("gas.pressure".to_owned(), Scalar::Float(15.0))]).unwrap();
+        assert_eq!(high.sim_params.light_yield, 12.0);
+        assert_eq!(high.sim_params.w_i        , conf.sim_params.w_i);
+        assert!(high.sim_params.el_range < conf.sim_params.el_range);
+        assert!(high.sim_params.cloud_r  < conf.sim_params.cloud_r);
+
+        // no light below the EL threshold
+        std::fs::write(&path, contents.replace("75 kV/cm", "5 kV/cm")).unwrap();
+        let err = SimConfig::new(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("below EL threshold"), "{err}");
     }
 }
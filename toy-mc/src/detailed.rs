@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use derive_new::new;
+
+use crate::SipmLayout;
+
+/// Binning of the fine image, a square grid of `n_bins` x `n_bins` covering
+/// [-extent, extent] in both axes.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Detailed {
+    /// Bins along each axis
+    pub n_bins: usize,
+    /// Half-width of the image, mm
+    pub extent: f64,
+}
+
+impl Detailed {
+    /// 100 x 100 bins over the full SiPM plane.
+    pub fn covering(layout: &SipmLayout) -> Self {
+        Self::new(100, layout.extent())
+    }
+
+    pub fn bins(&self) -> Vec<f64> {
+        let n = self.n_bins as f64;
+        (0..=self.n_bins)
+            .map(|i| -self.extent + (i as f64 / n) * 2.0 * self.extent)
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn bins() {
+        let bins = Detailed::new(4, 2.0).bins();
+        assert_eq!(bins.len(), 5);
+        assert_float_eq!(bins[0], -2.0, ulps<=2);
+        assert_float_eq!(bins[2],  0.0, ulps<=2);
+        assert_float_eq!(bins[4],  2.0, ulps<=2);
+    }
+}
@@ -1,10 +1,24 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use derive_new::new;
 
-#[derive(new, Debug, Deserialize, Serialize, Clone)]
+use crate::units;
+
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct ElGap {
+    /// Radius of the EL region, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub el_r        : f64,
+    /// Extent of the gap from the wires towards the SiPMs, mm. Descriptive
+    /// only: the light is collected at `geometry.buffer`
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub el_gap_front: f64,
+    /// Extent of the gap from the wires away from the SiPMs, mm. Descriptive
+    /// only
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub el_gap_back : f64,
 }
 
@@ -1,8 +1,17 @@
 use nalgebra::{DMatrix, Point2};
 
+/// Simulated event. `n_electrons`, `n_photons` and `n_detected` are truth:
+/// ionization electrons reaching the wires, photons emitted towards the SiPM
+/// plane and photons detected by the sensors, before dark counts and gain.
 pub struct Event {
-    pub number  : usize,
-    pub position: Point2<f64>,
-    pub wire_q  : Vec<usize>,
-    pub img     : DMatrix<usize>,
+    pub number     : usize,
+    pub position   : Point2<f64>,
+    pub n_electrons: usize,
+    pub n_photons  : usize,
+    pub n_detected : usize,
+    pub wire_q     : Vec<usize>,
+    pub wire_adc   : Option<Vec<usize>>,
+    pub img        : DMatrix<usize>,
+    pub fine_img   : Option<DMatrix<usize>>,
+    pub waveforms  : Option<DMatrix<f64>>,
 }
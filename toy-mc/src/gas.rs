@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use derive_new::new;
+
+use crate::units;
+
+/// Temperature at which the pressure-scaled properties are given, K.
+const ROOM_TEMPERATURE: f64 = 293.15;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Species {
+    #[serde(alias = "xe")]
+    Xe,
+    #[serde(alias = "ar")]
+    Ar,
+}
+
+/// Properties of a pure gas at room temperature. The EL yield is linear in
+/// the reduced field, `Y/p = el_slope * E/p - el_offset` photons per
+/// electron, cm and bar, with `E/p` in kV/(cm bar). Diffusion is the spread
+/// after 1 cm of drift at 1 bar, scaling as `sqrt(length / p)`.
+struct Properties {
+    w_i        : f64, // eV
+    fano_factor: f64,
+    el_slope   : f64,
+    el_offset  : f64,
+    diff_trans : f64, // mm
+    diff_long  : f64, // mm
+}
+
+impl Species {
+    /// W-values and Fano factors of the gas phase; EL yields from Monteiro et
+    /// al., JINST 2 P05001 (Xe) and PLB 668 167 (Ar); diffusion from Magboltz
+    /// at typical drift fields.
+    fn properties(self) -> Properties {
+        match self {
+            Species::Xe => Properties{ w_i: 21.9, fano_factor: 0.15, el_slope: 140.0, el_offset: 116.0, diff_trans: 3.3, diff_long: 1.4 },
+            Species::Ar => Properties{ w_i: 26.4, fano_factor: 0.20, el_slope:  81.0, el_offset:  47.0, diff_trans: 1.0, diff_long: 0.35 },
+        }
+    }
+}
+
+/// Single species, `"Xe"`, or molar fractions, `{ Xe = 0.9, Ar = 0.1 }`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(untagged)]
+pub enum Composition {
+    Pure   (Species),
+    Mixture(BTreeMap<Species, f64>),
+}
+
+/// Gas filling the detector, from which the `sim_params` and diffusion
+/// entries not given explicitly are derived. Mixtures average the
+/// properties of their components by molar fraction, which is only a rough
+/// approximation.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Gas {
+    /// Single species or molar fractions of a mixture
+    pub species     : Composition,
+    /// Pressure, bar
+    #[serde(deserialize_with = "units::pressure")]
+    #[schemars(with = "units::Quantity")]
+    pub pressure    : f64,
+    /// Temperature, K. 20 °C by default
+    #[serde(default = "room_temperature")]
+    pub temperature : f64,
+    /// Field at the surface of the wires, kV/cm. Derives `light_yield` and
+    /// `el_range`
+    #[serde(default, deserialize_with = "units::optional_field", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<units::Quantity>")]
+    pub el_field    : Option<f64>,
+    /// Drift length, mm. Derives `cloud_r` and, with `[timing]`,
+    /// `long_diffusion`
+    #[serde(default, deserialize_with = "units::optional_length", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<units::Quantity>")]
+    pub drift_length: Option<f64>,
+}
+
+fn room_temperature() -> f64 {
+    ROOM_TEMPERATURE
+}
+
+impl Gas {
+    /// Properties of each component and its normalized fraction.
+    fn components(&self) -> Vec<(Properties, f64)> {
+        match &self.species {
+            Composition::Pure(s)    => vec![(s.properties(), 1.0)],
+            Composition::Mixture(m) => {
+                let total : f64 = m.values().sum();
+                m.iter().map(|(s, f)| (s.properties(), f / total)).collect()
+            }
+        }
+    }
+
+    fn average(&self, property: impl Fn(&Properties) -> f64) -> f64 {
+        self.components().iter().map(|(p, f)| f * property(p)).sum()
+    }
+
+    /// Pressure at room temperature with the same density, bar.
+    pub fn reduced_pressure(&self) -> f64 {
+        self.pressure * ROOM_TEMPERATURE / self.temperature
+    }
+
+    /// Mean energy to produce an ionization electron, eV. Mixtures add the
+    /// ionization rates of their components.
+    pub fn w_i(&self) -> f64 {
+        1.0 / self.average(|p| 1.0 / p.w_i)
+    }
+
+    pub fn fano_factor(&self) -> f64 {
+        self.average(|p| p.fano_factor)
+    }
+
+    /// Field above which electrons produce EL light, kV/cm.
+    pub fn el_threshold(&self) -> f64 {
+        self.reduced_pressure() * self.average(|p| p.el_offset) / self.average(|p| p.el_slope)
+    }
+
+    /// Distance from the wire surface within which the field exceeds the EL
+    /// threshold, mm, for wires of radius `wire_r`. The field falls as `1/r`.
+    pub fn el_range(&self, wire_r: f64) -> Option<f64> {
+        let r_max = wire_r * self.el_field? / self.el_threshold();
+        Some((r_max - wire_r).max(0.0))
+    }
+
+    /// EL photons emitted per electron drifting to a wire of radius
+    /// `wire_r`, integrating the yield over the range above threshold.
+    pub fn light_yield(&self, wire_r: f64) -> Option<f64> {
+        let range  = self.el_range(wire_r)?;
+        let (a, r) = (wire_r * 0.1, (wire_r + range) * 0.1); // cm
+        let light  = self.average(|p| p.el_slope) * self.el_field? * a * (r / a).ln()
+                   - self.average(|p| p.el_offset) * self.reduced_pressure() * (r - a);
+        Some(light.max(0.0))
+    }
+
+    /// Transverse and longitudinal spread of the electrons at the end of the
+    /// drift, mm.
+    pub fn diffusion(&self) -> Option<(f64, f64)> {
+        let scale = (self.drift_length? * 0.1 / self.reduced_pressure()).sqrt();
+        Some((self.average(|p| p.diff_trans) * scale, self.average(|p| p.diff_long) * scale))
+    }
+
+    /// Configuration entries derived from the gas, as dotted paths, for wires
+    /// of radius `wire_r` and, with the time response, a drift of
+    /// `drift_time` ns. The radius of the uniform cloud matches the RMS of
+    /// the transverse diffusion.
+    pub fn derived(&self, wire_r: f64, drift_time: Option<f64>) -> Vec<(String, f64)> {
+        let diffusion = self.diffusion();
+        let entries   = [ ("sim_params.w_i"        , Some(self.w_i()))
+                        , ("sim_params.fano_factor", Some(self.fano_factor()))
+                        , ("sim_params.light_yield", self.light_yield(wire_r))
+                        , ("sim_params.el_range"   , self.el_range(wire_r))
+                        , ("sim_params.cloud_r"    , diffusion.map(|(t, _)| 2.0 * t))
+                        , ("timing.long_diffusion" , diffusion.zip(drift_time).zip(self.drift_length)
+                                                              .map(|(((_, l), t), d)| l * t / d))
+                        ];
+        entries.into_iter()
+               .filter_map(|(key, value)| value.map(|v| (key.to_owned(), v)))
+               .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn xenon(pressure: f64) -> Gas {
+        Gas::new(Composition::Pure(Species::Xe), pressure, ROOM_TEMPERATURE, Some(75.0), Some(300.0))
+    }
+
+    #[test]
+    fn pure_xenon() {
+        let gas = xenon(10.0);
+        assert_float_eq!(gas.w_i()        , 21.9, rmax<=1e-12);
+        assert_float_eq!(gas.fano_factor(), 0.15, rmax<=1e-12);
+        // 5 um wires: the field falls below 116/140 kV/(cm bar) about 40 um away
+        let range = gas.el_range(5e-3).unwrap();
+        assert_float_eq!(range, 5e-3 * (75.0 / (10.0 * 116.0 / 140.0) - 1.0), rmax<=1e-12);
+        assert!((range - 0.040).abs() < 0.002, "{range}");
+        let light = gas.light_yield(5e-3).unwrap();
+        assert!(light > 5.0 && light < 10.0, "{light}");
+        // about 1 mm/sqrt(cm) at 10 bar
+        let (trans, long) = gas.diffusion().unwrap();
+        assert_float_eq!(trans, 3.3 * (30.0f64 / 10.0).sqrt(), rmax<=1e-12);
+        assert!(long < trans);
+    }
+
+    #[test]
+    fn pressure_scaling() {
+        let (low, high) = (xenon(5.0), xenon(10.0));
+        assert_float_eq!(low.w_i(), high.w_i(), rmax<=1e-12);
+        assert!(low.el_range(5e-3).unwrap() > high.el_range(5e-3).unwrap());
+        assert_float_eq!(low.diffusion().unwrap().0 / high.diffusion().unwrap().0, 2f64.sqrt(), rmax<=1e-12);
+
+        // the same density when hotter and at a higher pressure
+        let hot = Gas{temperature: 2.0 * ROOM_TEMPERATURE, ..xenon(10.0)};
+        assert_float_eq!(hot.diffusion().unwrap().0, low.diffusion().unwrap().0, rmax<=1e-12);
+
+        // below threshold
+        let weak = Gas{el_field: Some(5.0), ..xenon(10.0)};
+        assert_eq!(weak.el_range(5e-3), Some(0.0));
+        assert_eq!(weak.light_yield(5e-3), Some(0.0));
+    }
+
+    #[test]
+    fn mixtures() {
+        let gas : Gas = toml::from_str("species = { Xe = 3, ar = 1 }\npressure = \"1 atm\"").unwrap();
+        assert_float_eq!(gas.pressure, 1.01325, rmax<=1e-12);
+        assert_float_eq!(gas.fano_factor(), 0.75 * 0.15 + 0.25 * 0.20, rmax<=1e-12);
+        assert!(gas.w_i() > 21.9 && gas.w_i() < 26.4);
+        assert!(gas.el_range(5e-3).is_none());
+        assert!(gas.diffusion().is_none());
+
+        let keys = gas.derived(5e-3, Some(1000.0)).into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys, ["sim_params.w_i", "sim_params.fano_factor"]);
+        assert_eq!(xenon(10.0).derived(5e-3, Some(1000.0)).len(), 6);
+        assert_eq!(xenon(10.0).derived(5e-3, None).len(), 5);
+    }
+}
@@ -1,16 +1,29 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use derive_new::new;
 
 use crate::sipm_plane::SipmPlane;
 use crate::wire_plane::WirePlane;
 use crate::el_gap    ::ElGap;
+use crate::mesh      ::Mesh;
+use crate::units;
 
-#[derive(new, Debug, Deserialize, Serialize, Clone)]
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct Geometry {
+    /// Anode wires, where the electrons end and emit light
     pub wire_plane: WirePlane,
+    /// Sensors detecting the light
     pub sipm_plane: SipmPlane,
+    /// Extent of the EL region around the wires
     pub el_gap    : ElGap,
-    pub buffer    : f64
+    /// Distance from the wire plane to the SiPM plane, where the light is
+    /// collected, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
+    pub buffer    : f64,
+    /// Meshes or windows between the wires and the SiPMs, none by default
+    #[serde(default)]
+    pub meshes    : Vec<Mesh>,
 }
 
 #[cfg(test)]
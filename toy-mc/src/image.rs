@@ -1,45 +1,60 @@
+use std::io;
 use nalgebra::{Point2, DMatrix};
-use ndhistogram::{Histogram, Hist2D, axis::VariableNoFlow, ndhistogram};
 
+use crate::{SipmPlane, SipmLayout, Detailed};
+
+/// Counts of the sensors of a layout. Hits are mapped to sensors
+/// arithmetically (or through a cell lookup for sensor lists), those falling
+/// in the gaps are lost. Meant to be built once and `reset` between events.
 pub struct Image {
-    n_bins: usize,
-    hist  : Hist2D<VariableNoFlow<f64>, VariableNoFlow<f64>, usize>,
+    layout: SipmLayout,
+    data  : DMatrix<usize>,
 }
 
 impl Image {
-    pub fn new(bins: &Vec<f64>) -> Self {
-        let n_bins = bins.len() - 1;
-        let vbins  = VariableNoFlow::new(bins.clone()).unwrap();
-        let hist   = ndhistogram!(vbins.clone(), vbins; usize);
+    pub fn new(layout: SipmLayout) -> Self {
+        let (rows, cols) = layout.shape();
+        Self{ layout, data: DMatrix::zeros(rows, cols) }
+    }
+
+    /// Square grid of `n` x `n` sensors of width `size`, placed every `pitch`
+    /// and centred at the origin.
+    pub fn square(n: usize, pitch: f64, size: f64) -> Self {
+        Self::new(SipmLayout::grid((n, n), (size, size), (pitch - size, pitch - size), (0.0, 0.0)))
+    }
+
+    pub fn for_sipms(sipms: &SipmPlane) -> io::Result<Self> {
+        Ok(Self::new(sipms.layout()?))
+    }
+
+    /// Gapless grid of the fine image.
+    pub fn for_detailed(detailed: &Detailed) -> Self {
+        let pitch = 2.0 * detailed.extent / detailed.n_bins as f64;
+        Self::square(detailed.n_bins, pitch, pitch)
+    }
 
-        Self{ n_bins, hist }
+    pub fn layout(&self) -> &SipmLayout {
+        &self.layout
+    }
+
+    /// (row, column) of the sensor containing `p`.
+    pub fn index(&self, p: &Point2<f64>) -> Option<(usize, usize)> {
+        self.layout.locate(p)
     }
 
     pub fn fill(&mut self, p: &Point2<f64>) {
-        self.hist.fill(&(p.x, p.y));
+        if let Some(idx) = self.index(p) {
+            self.data[idx] += 1;
+        }
     }
 
-    pub fn data(&self) -> Vec<usize> {
-        self.hist.iter().map(|b| b.value).cloned().collect()
+    pub fn reset(&mut self) {
+        self.data.fill(0);
     }
 
+    /// Accumulated image, rows along y for grids.
     pub fn finalize(&self) -> DMatrix<usize> {
-        // Read column-wise because DMatrix
-        let n = self.n_bins;
-        let v : Vec<usize> =
-            self.hist
-                .iter()
-                .enumerate()
-                .map   (|(i,v  )| (i/n, i.rem_euclid(n), v))
-                .filter(|(r,_,_)| (*r > 0) & (*r < n-1)) // skip first and last rows
-                .filter(|(_,c,_)| (*c > 0) & (*c < n-1)) // skip first and last columns
-                .filter(|(r,_,_)| (r-1).rem_euclid(3) == 0)
-                .filter(|(_,c,_)| (c-1).rem_euclid(3) == 0)
-                .map   (|(_,_,b)| b.value)
-                .copied()
-                .collect();
-        let n = n/3;
-        DMatrix::from_vec(n, n, v).transpose()
+        self.data.clone()
     }
 }
 
@@ -52,8 +67,8 @@ mod tests {
 
     #[test]
     fn simple() {
-        let bins  = vec![-7.0, -6.5, -0.5, 0.0, 0.5, 6.5, 7.0];
-        let mut hist = Image::new(&bins);
+        // 2 x 2 sensors of 6.0 with a 1.0 gap
+        let mut hist = Image::square(2, 7.0, 6.0);
         hist.fill(&point!(-6.0, -5.5)); // bin 0
         hist.fill(&point!( 5.5, -5.0)); // bin 1
         hist.fill(&point!( 4.0, -4.5));
@@ -73,20 +88,72 @@ mod tests {
         assert_eq!(m[(1, 1)], 4);
     }
 
+    #[test]
+    fn gaps_and_outside_lost() {
+        let mut hist = Image::square(2, 7.0, 6.0);
+        hist.fill(&point!( 0.0,  3.5)); // gap
+        hist.fill(&point!( 3.5, -0.2)); // gap
+        hist.fill(&point!( 6.8,  3.5)); // outer gap
+        hist.fill(&point!(-3.5,  9.0)); // outside
+        assert_eq!(hist.finalize().sum(), 0);
+    }
+
     #[test]
     fn hist_order() {
-        let bins  = vec![-0.5, 0.0, 0.5];
-        let mut hist = Image::new(&bins);
+        let mut hist = Image::square(2, 0.5, 0.5);
         for _ in 0..1 { hist.fill(&point!(-0.1, -0.1)); } // bin 0
         for _ in 0..2 { hist.fill(&point!( 0.1, -0.1)); } // bin 1
         for _ in 0..3 { hist.fill(&point!(-0.1,  0.1)); } // bin 2
         for _ in 0..4 { hist.fill(&point!( 0.1,  0.1)); } // bin 3
-        let m = hist.data();
-        let expected = vec![1, 2, 3, 4];
-        m.iter()
-         .zip(expected.iter())
-         .for_each(|(got, exp)| {
-            assert_eq!(got, exp);
-        });
+        let m = hist.finalize();
+        assert_eq!(m[(0, 0)], 1);
+        assert_eq!(m[(0, 1)], 2);
+        assert_eq!(m[(1, 0)], 3);
+        assert_eq!(m[(1, 1)], 4);
+    }
+
+    #[test]
+    fn reset() {
+        let mut hist = Image::square(2, 0.5, 0.5);
+        hist.fill(&point!(0.1, 0.1));
+        hist.reset();
+        assert_eq!(hist.finalize().sum(), 0);
+        hist.fill(&point!(0.1, 0.1));
+        assert_eq!(hist.finalize()[(1, 1)], 1);
+    }
+
+    #[test]
+    fn same_as_sipm_plane() {
+        let plane = SipmPlane::square(6.0, 34.8, 0.5, 10);
+        let img   = Image::for_sipms(&plane).unwrap();
+        let bins  = plane.sipm_bins();
+        for _ in 0..10_000 {
+            let p      = crate::random::random_in_circle(35.0);
+            let bin    = |u: f64| bins.partition_point(|&b| b <= u).checked_sub(1)
+                                      .filter(|&k| k < bins.len() - 1 && k % 3 == 1)
+                                      .map   (|k| k / 3);
+            let expected = bin(p.y).zip(bin(p.x));
+            assert_eq!(img.index(&p), expected, "failed for {p}");
+        }
+    }
+
+    #[test]
+    fn rectangular() {
+        let layout   = SipmLayout::grid((3, 1), (1.0, 1.0), (0.0, 0.0), (0.0, 0.0));
+        let mut hist = Image::new(layout);
+        hist.fill(&point!( 1.2, 0.0));
+        hist.fill(&point!(-1.2, 0.3));
+        let m = hist.finalize();
+        assert_eq!(m.shape(), (1, 3));
+        assert_eq!(m[(0, 0)], 1);
+        assert_eq!(m[(0, 2)], 1);
+    }
+
+    #[test]
+    fn detailed_covers_extent() {
+        let img = Image::for_detailed(&Detailed::new(4, 2.0));
+        assert_eq!(img.index(&point!(-1.9, -1.9)), Some((0, 0)));
+        assert_eq!(img.index(&point!( 1.9, -0.1)), Some((1, 3)));
+        assert_eq!(img.index(&point!( 2.1,  0.0)), None);
     }
 }
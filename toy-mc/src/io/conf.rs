@@ -11,3 +11,21 @@ pub fn write_conf(filename: &str, conf: &SimConfig) -> io::Result<()> {
     let contents = toml::to_string(conf).expect("Could not serialize config");
     file.write_all(contents.as_bytes())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::Builder;
+
+    #[test]
+    fn roundtrip() {
+        let conf     = SimConfig::new("conf/test.toml").unwrap();
+        let file     = Builder::new().suffix(".conf").tempfile().unwrap();
+        let filename = file.path().to_str().unwrap();
+        write_conf(filename, &conf).unwrap();
+        let read     = SimConfig::new(filename).unwrap();
+        assert_eq!(toml::to_string(&read).unwrap(), toml::to_string(&conf).unwrap());
+    }
+}
@@ -1,11 +1,15 @@
 use std::fs::File;
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
+use std::fs::read_to_string;
 use nalgebra::DMatrix;
 use nalgebra::RowDVector;
 use itertools::Itertools;
 
-use crate::{Event, SimConfig};
+use crate::{Event, SimConfig, SipmLayout, Provenance};
+use crate::io::EventWriter;
+use crate::io::read::Table;
 
 fn _row_as_str(row: RowDVector<usize>) -> String {
     #[allow(unstable_name_collisions)]
@@ -36,7 +40,7 @@ fn img_as_str_1d(img: &DMatrix<usize>) -> String {
        .collect()
 }
 
-fn vec_as_str<T: ToString>(vec: &Vec<T>) -> String {
+fn vec_as_str<T: ToString>(vec: &[T]) -> String {
     #[allow(unstable_name_collisions)]
     vec.iter()
        .map(T::to_string)
@@ -54,13 +58,73 @@ pub fn write_img_1d(file: &mut File, img: &DMatrix<usize>) -> io::Result<()> {
     file.write_all(contents.as_bytes())
 }
 
-pub fn write_header(file: &mut File, n_wires: usize, img_size: usize) -> io::Result<()> {
+/// Writes `# key: value` comment lines. Multi-line values follow their key,
+/// one `# | line` per line.
+fn write_comments(file: &mut File, entries: &[(String, String)]) -> io::Result<()> {
+    let mut text = String::new();
+    for (key, value) in entries {
+        if value.contains('\n') {
+            text.push_str(&format!("# {key}:\n"));
+            value.lines().for_each(|l| text.push_str(&format!("# | {l}\n")));
+        } else {
+            text.push_str(&format!("# {key}: {value}\n"));
+        }
+    }
+    file.write_all(text.as_bytes())
+}
+
+/// Parses a line written by `write_comments`, appending continuation lines
+/// to the last key.
+fn read_comment(line: &str, metadata: &mut HashMap<String, String>, last: &mut Option<String>) {
+    let comment = line.trim_start_matches('#');
+    if let Some(value) = comment.strip_prefix(" |") {
+        if let Some(value_of_last) = last.as_ref().and_then(|k| metadata.get_mut(k)) {
+            value_of_last.push_str(value.strip_prefix(' ').unwrap_or(value));
+            value_of_last.push('\n');
+        }
+    } else if let Some((key, value)) = comment.split_once(':') {
+        let key = key.trim().to_owned();
+        metadata.insert(key.clone(), value.trim().to_owned());
+        *last = Some(key);
+    }
+}
+
+/// Text file starting with the provenance of the run as comments. The end
+/// of the run is appended when dropped, since the writer closures are never
+/// closed explicitly.
+struct CsvFile {
+    file    : File,
+    n_events: usize,
+}
+
+impl CsvFile {
+    fn create(filename: &str, conf: &SimConfig) -> io::Result<Self> {
+        let mut file = File::create(filename)?;
+        write_comments(&mut file, &Provenance::new(conf).header())?;
+        Ok(Self{file, n_events: 0})
+    }
+}
+
+impl Drop for CsvFile {
+    fn drop(&mut self) {
+        // errors cannot be returned from drop, and panicking here may abort
+        if let Err(e) = write_comments(&mut self.file, &Provenance::footer(self.n_events)) {
+            eprintln!("error: could not complete CSV file: {e}");
+        }
+    }
+}
+
+pub fn write_header(file: &mut File, n_wires: usize, n_adc: usize, (img_rows, img_cols): (usize, usize), fine_size: usize) -> io::Result<()> {
     let mut line = String::new();
-    line.push_str("event x0 y0");
-    (0..n_wires).into_iter().for_each(|w| line.push_str(&format!(" w_{}", w)));
-    (0..img_size).into_iter()
-                 .flat_map(|i| (0..img_size).into_iter().map(move |j| (i,j)))
+    line.push_str("event x0 y0 n_e n_ph n_det");
+    (0..n_wires).for_each(|w| line.push_str(&format!(" w_{}", w)));
+    (0..n_adc  ).for_each(|w| line.push_str(&format!(" adc_{}", w)));
+    (0..img_rows)
+                 .flat_map(|i| (0..img_cols).map(move |j| (i,j)))
                  .for_each(|(i,j)| line.push_str(&format!(" img_{}_{}", i, j)));
+    (0..fine_size)
+                 .flat_map(|i| (0..fine_size).map(move |j| (i,j)))
+                 .for_each(|(i,j)| line.push_str(&format!(" fine_{}_{}", i, j)));
     line.push('\n');
     file.write_all(line.as_bytes())
 }
@@ -70,17 +134,101 @@ fn write_event(file: &mut File, event: &Event) -> io::Result<()> {
     line.push_str(&event.number    .to_string()); line.push(' ');
     line.push_str(&event.position.x.to_string()); line.push(' ');
     line.push_str(&event.position.y.to_string()); line.push(' ');
+    line.push_str(&event.n_electrons.to_string()); line.push(' ');
+    line.push_str(&event.n_photons  .to_string()); line.push(' ');
+    line.push_str(&event.n_detected .to_string()); line.push(' ');
     line.push_str(&vec_as_str(&event.wire_q)   ); line.push(' ');
-    line.push_str(&img_as_str_1d(&event.img)   ); line.push('\n');
+    if let Some(adc) = &event.wire_adc {
+        line.push_str(&vec_as_str(adc)); line.push(' ');
+    }
+    line.push_str(&img_as_str_1d(&event.img)   );
+    if let Some(fine) = &event.fine_img {
+        line.push(' ');
+        line.push_str(&img_as_str_1d(fine));
+    }
+    line.push('\n');
+    file.write_all(line.as_bytes())
+}
+
+pub fn write_waveform_header(file: &mut File, n_samples: usize) -> io::Result<()> {
+    let mut line = String::new();
+    line.push_str("event sensor");
+    (0..n_samples).for_each(|i| line.push_str(&format!(" s_{}", i)));
+    line.push('\n');
     file.write_all(line.as_bytes())
 }
 
-pub fn get_writer(filename: &str, conf: &SimConfig) -> Box<dyn FnMut(&Event) -> io::Result<()>> {
-    let mut file = File::create(filename).unwrap();
-    write_header(&mut file, conf.geometry.wire_plane.n_wires, conf.geometry.sipm_plane.n_sipms_side).unwrap();
-    Box::new( move |e: &Event| {
-        write_event(&mut file, e)
-    })
+/// One line per channel with signal. Channels without signal are skipped.
+fn write_waveforms(file: &mut File, event: &Event) -> io::Result<()> {
+    let Some(wfs) = &event.waveforms else { return Ok(()) };
+    let mut contents = String::new();
+    for (ch, row) in wfs.row_iter().enumerate() {
+        if row.iter().all(|&q| q == 0.0) { continue }
+        contents.push_str(&event.number.to_string()); contents.push(' ');
+        contents.push_str(&ch          .to_string()); contents.push(' ');
+        #[allow(unstable_name_collisions)]
+        contents.extend(row.iter()
+                           .map(f64::to_string)
+                           .intersperse(" ".to_owned()));
+        contents.push('\n');
+    }
+    file.write_all(contents.as_bytes())
+}
+
+pub fn get_writer(filename: &str, conf: &SimConfig, layout: &SipmLayout) -> io::Result<EventWriter> {
+    let mut csv   = CsvFile::create(filename, conf)?;
+    let fine_size = conf.detailed.as_ref().map_or(0, |d| d.n_bins);
+    let n_wires   = conf.geometry.wire_plane.n_wires;
+    let n_adc     = if conf.wire_readout().is_some() { n_wires } else { 0 };
+    write_header(&mut csv.file, n_wires, n_adc, layout.shape(), fine_size)?;
+    Ok(Box::new( move |e: &Event| {
+        csv.n_events += 1;
+        write_event(&mut csv.file, e)
+    }))
+}
+
+pub fn get_waveform_writer(filename: &str, conf: &SimConfig) -> io::Result<EventWriter> {
+    let n_samples = conf.timing.as_ref().map_or(0, |t| t.n_samples);
+    let mut csv   = CsvFile::create(filename, conf)?;
+    write_waveform_header(&mut csv.file, n_samples)?;
+    Ok(Box::new( move |e: &Event| {
+        csv.n_events += 1;
+        write_waveforms(&mut csv.file, e)
+    }))
+}
+
+/// Reads a table written by `get_writer`: a header line with the column
+/// names followed by one line of values per event. Comment lines go to the
+/// metadata.
+pub fn read_table(filename: &str) -> io::Result<Table> {
+    let contents = read_to_string(filename)?;
+    let invalid  = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}: {msg}"));
+    let mut metadata = HashMap::new();
+    let mut last_key = None;
+    let mut names    = None;
+    let mut rows     = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.starts_with('#') {
+            read_comment(line, &mut metadata, &mut last_key);
+            continue
+        }
+        if line.trim().is_empty() { continue }
+        let Some(names) = &names else {
+            names = Some(line.split_whitespace().map(str::to_owned).collect::<Vec<_>>());
+            continue
+        };
+        let row : Vec<f64> =
+            line.split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|e| invalid(format!("line {}: {e}", i+1)))?;
+        if row.len() != names.len() {
+            return Err(invalid(format!("line {}: expected {} columns, found {}", i+1, names.len(), row.len())))
+        }
+        rows.push(row);
+    }
+    let names = names.ok_or_else(|| invalid("empty file".to_owned()))?;
+    Ok(Table{names, rows, metadata})
 }
 
 #[cfg(test)]
@@ -140,12 +288,68 @@ mod tests {
     #[test]
     fn header_write() {
         let mut file = tempfile().unwrap();
-        write_header(&mut file, 3, 2).unwrap();
+        write_header(&mut file, 3, 0, (2, 2), 0).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!("event x0 y0 n_e n_ph n_det w_0 w_1 w_2 img_0_0 img_0_1 img_1_0 img_1_1\n", buffer);
+    }
+
+    #[test]
+    fn header_write_rectangular() {
+        let mut file = tempfile().unwrap();
+        write_header(&mut file, 0, 0, (1, 3), 0).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!("event x0 y0 n_e n_ph n_det img_0_0 img_0_1 img_0_2\n", buffer);
+    }
+
+    #[test]
+    fn header_write_fine() {
+        let mut file = tempfile().unwrap();
+        write_header(&mut file, 1, 0, (1, 1), 2).unwrap();
         file.seek(io::SeekFrom::Start(0)).unwrap();
 
         let mut buffer = String::new();
         file.read_to_string(&mut buffer).unwrap();
-        assert_eq!("event x0 y0 w_0 w_1 w_2 img_0_0 img_0_1 img_1_0 img_1_1\n", buffer);
+        assert_eq!("event x0 y0 n_e n_ph n_det w_0 img_0_0 fine_0_0 fine_0_1 fine_1_0 fine_1_1\n", buffer);
+    }
+
+    #[test]
+    fn header_write_adc() {
+        let mut file = tempfile().unwrap();
+        write_header(&mut file, 2, 2, (1, 1), 0).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!("event x0 y0 n_e n_ph n_det w_0 w_1 adc_0 adc_1 img_0_0\n", buffer);
+    }
+
+    #[test]
+    fn event_write_adc() {
+        let e = Event{
+            number: 5,
+            position: point!(0.5, 1.5),
+            n_electrons: 0,
+            n_photons: 0,
+            n_detected: 0,
+            wire_q: vec![10, 20],
+            wire_adc: Some(vec![0, 41]),
+            img: DMatrix::from_vec(1, 1, vec![7usize]),
+            fine_img: None,
+            waveforms: None,
+        };
+        let mut file = tempfile().unwrap();
+        write_event(&mut file, &e).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!("5 0.5 1.5 0 0 0 10 20 0 41 7\n", buffer);
     }
 
     #[test]
@@ -153,8 +357,37 @@ mod tests {
         let e = Event{
             number: 123,
             position: point!(4.56, 7.89),
+            n_electrons: 10,
+            n_photons: 200,
+            n_detected: 30,
             wire_q: vec![3, 1, 4, 15, 92, 65, 35, 89, 79],
+            wire_adc: None,
             img: DMatrix::from_vec(2, 2, vec![1usize, 100, 10, 1000]),
+            fine_img: None,
+            waveforms: None,
+        };
+        let mut file = tempfile().unwrap();
+        write_event(&mut file, &e).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!("123 4.56 7.89 10 200 30 3 1 4 15 92 65 35 89 79 1 10 100 1000\n", buffer);
+    }
+
+    #[test]
+    fn event_write_fine() {
+        let e = Event{
+            number: 4,
+            position: point!(0.5, 1.5),
+            n_electrons: 0,
+            n_photons: 0,
+            n_detected: 0,
+            wire_q: vec![2],
+            wire_adc: None,
+            img: DMatrix::from_vec(1, 1, vec![3usize]),
+            fine_img: Some(DMatrix::from_vec(2, 2, vec![1usize, 3, 2, 4])),
+            waveforms: None,
         };
         let mut file = tempfile().unwrap();
         write_event(&mut file, &e).unwrap();
@@ -162,7 +395,55 @@ mod tests {
 
         let mut buffer = String::new();
         file.read_to_string(&mut buffer).unwrap();
-        assert_eq!("123 4.56 7.89 3 1 4 15 92 65 35 89 79 1 10 100 1000\n", buffer);
+        assert_eq!("4 0.5 1.5 0 0 0 2 3 1 2 3 4\n", buffer);
+    }
+
+    #[test]
+    fn waveforms_write() {
+        let wfs = DMatrix::from_row_slice(3, 2, &[0.0, 1.5, 0.0, 0.0, 2.0, 0.25]);
+        let e = Event{
+            number: 7,
+            position: point!(0.0, 0.0),
+            n_electrons: 0,
+            n_photons: 0,
+            n_detected: 0,
+            wire_q: vec![],
+            wire_adc: None,
+            img: DMatrix::zeros(0, 0),
+            fine_img: None,
+            waveforms: Some(wfs),
+        };
+        let mut file = tempfile().unwrap();
+        write_waveform_header(&mut file, 2).unwrap();
+        write_waveforms(&mut file, &e).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!("event sensor s_0 s_1\n7 0 0 1.5\n7 2 2 0.25\n", buffer);
+    }
+
+    #[test]
+    fn comments_roundtrip() {
+        let entries = vec![ ("seed"  .to_owned(), "42"               .to_owned())
+                          , ("config".to_owned(), "a = 1\n\n[b]\nc = 2\n".to_owned())
+                          , ("end"   .to_owned(), "2000-01-01T00:00:00Z".to_owned())
+                          ];
+        let mut file = tempfile().unwrap();
+        write_comments(&mut file, &entries).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer).unwrap();
+        assert_eq!(buffer.lines().nth(1), Some("# config:"));
+
+        let mut metadata = HashMap::new();
+        let mut last     = None;
+        buffer.lines().for_each(|l| read_comment(l, &mut metadata, &mut last));
+        assert_eq!(metadata.len(), 3);
+        for (key, value) in entries {
+            assert_eq!(metadata[&key], value);
+        }
     }
 
     #[test]
@@ -1,51 +1,346 @@
 use std::fs::File;
-use std::sync::Arc;
 use std::io;
+use std::sync::Arc;
 
-use arrow::array::{UInt32Array, Float32Array, ArrayRef};
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::array::{UInt32Array, Int32Array, Float32Array, Float64Array, BooleanArray, ArrayRef, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, Float64Type};
 use arrow::record_batch::RecordBatch;
+use arrow::ipc::reader::FileReader;
 use arrow::ipc::writer::FileWriter;
 
-use crate::{Event, SimConfig};
+use crate::{Event, SimConfig, SipmLayout, PhotonRecord, Lut, Provenance};
+use crate::io::{EventWriter, PhotonWriter};
+use crate::io::read::Table;
+
+
+/// Arrow IPC file that writes its footer when dropped, since the writer
+/// closures are never closed explicitly. Files of a run store its
+/// provenance in the schema metadata, and the end of the run in the footer.
+struct FeatherFile {
+    writer  : FileWriter<File>,
+    n_events: Option<usize>,
+}
+
+impl FeatherFile {
+    fn create(filename: &str, schema: &Schema) -> io::Result<Self> {
+        let file   = File::create(filename)?;
+        let writer = FileWriter::try_new(file, schema).map_err(|e| io::Error::other(format!("{filename}: {e}")))?;
+        Ok(Self{writer, n_events: None})
+    }
+
+    fn for_run(filename: &str, schema: &Schema, conf: &SimConfig) -> io::Result<Self> {
+        let metadata = Provenance::new(conf).header().into_iter().collect();
+        let schema   = schema.clone().with_metadata(metadata);
+        let mut file = Self::create(filename, &schema)?;
+        file.n_events = Some(0);
+        Ok(file)
+    }
+
+    fn write(&mut self, rb: &RecordBatch) {
+        self.writer.write(rb).unwrap();
+    }
+
+    fn count_event(&mut self) {
+        if let Some(n) = self.n_events.as_mut() { *n += 1; }
+    }
+}
+
+impl Drop for FeatherFile {
+    fn drop(&mut self) {
+        if let Some(n) = self.n_events {
+            Provenance::footer(n).into_iter().for_each(|(k, v)| self.writer.write_metadata(k, v));
+        }
+        // errors cannot be returned from drop, and panicking here may abort
+        if let Err(e) = self.writer.finish() {
+            eprintln!("error: could not complete Feather file: {e}");
+        }
+    }
+}
 
 
-pub fn generate_schema(n_wires: usize, n_sipms: usize) -> Arc<Schema> {
+pub fn generate_schema(n_wires: usize, n_adc: usize, (img_rows, img_cols): (usize, usize), n_fine: usize) -> Arc<Schema> {
     let mut fields = vec![
         Field::new("event", DataType::UInt32 , false),
         Field::new(    "x", DataType::Float32, false),
         Field::new(    "y", DataType::Float32, false),
+        Field::new(  "n_e", DataType::UInt32 , false),
+        Field::new( "n_ph", DataType::UInt32 , false),
+        Field::new("n_det", DataType::UInt32 , false),
     ];
     for i in 0..n_wires {
         let name = format!("wire_{i}");
         fields.push(Field::new(name, DataType::UInt32, false));
     }
-    for i in 0..n_sipms {
-        for j in 0..n_sipms {
+    for i in 0..n_adc {
+        let name = format!("adc_{i}");
+        fields.push(Field::new(name, DataType::UInt32, false));
+    }
+    for i in 0..img_rows {
+        for j in 0..img_cols {
             let name = format!("img_{i}_{j}");
             fields.push(Field::new(name, DataType::UInt32, false))
         }
     }
+    for i in 0..n_fine {
+        for j in 0..n_fine {
+            let name = format!("fine_{i}_{j}");
+            fields.push(Field::new(name, DataType::UInt32, false))
+        }
+    }
     Arc::new(Schema::new(fields))
 }
 
 fn create_record_batch(e: &Event, s: Arc<Schema>) -> RecordBatch {
-    let mut fields : Vec<ArrayRef> = Vec::new();
-    fields.push(Arc::new( UInt32Array::from(vec![e.number     as u32])));
-    fields.push(Arc::new(Float32Array::from(vec![e.position.x as f32])));
-    fields.push(Arc::new(Float32Array::from(vec![e.position.y as f32])));
+    let mut fields : Vec<ArrayRef> = vec![
+        Arc::new( UInt32Array::from(vec![e.number      as u32])),
+        Arc::new(Float32Array::from(vec![e.position.x  as f32])),
+        Arc::new(Float32Array::from(vec![e.position.y  as f32])),
+        Arc::new( UInt32Array::from(vec![e.n_electrons as u32])),
+        Arc::new( UInt32Array::from(vec![e.n_photons   as u32])),
+        Arc::new( UInt32Array::from(vec![e.n_detected  as u32])),
+    ];
     for q in &e.wire_q { fields.push(Arc::new(UInt32Array::from(vec![*q as u32]))); }
-    for q in &e.img    { fields.push(Arc::new(UInt32Array::from(vec![*q as u32]))); }
+    for q in e.wire_adc.iter().flatten() { fields.push(Arc::new(UInt32Array::from(vec![*q as u32]))); }
+    // Images are stored column-major, columns are named row-major
+    for q in &e.img.transpose() { fields.push(Arc::new(UInt32Array::from(vec![*q as u32]))); }
+    if let Some(fine) = &e.fine_img {
+        for q in &fine.transpose() { fields.push(Arc::new(UInt32Array::from(vec![*q as u32]))); }
+    }
     RecordBatch::try_new(s.clone(), fields).unwrap()
 }
 
-pub fn get_writer(filename: &str, conf: &SimConfig) -> Box<dyn FnMut(&Event) -> io::Result<()>> {
-    let     schema = generate_schema(conf.geometry.wire_plane.n_wires, conf.geometry.sipm_plane.n_sipms_side);
-    let     file   = File::create(filename).unwrap();
-    let mut writer = FileWriter::try_new(file, &schema).unwrap();
-    Box::new( move |e: &Event| {
+pub fn generate_waveform_schema(n_samples: usize) -> Arc<Schema> {
+    let mut fields = vec![
+        Field::new( "event", DataType::UInt32, false),
+        Field::new("sensor", DataType::UInt32, false),
+    ];
+    for i in 0..n_samples {
+        let name = format!("s_{i}");
+        fields.push(Field::new(name, DataType::Float32, false));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+/// One row per channel with signal. Channels without signal are skipped.
+fn create_waveform_batch(e: &Event, s: Arc<Schema>) -> Option<RecordBatch> {
+    let wfs      = e.waveforms.as_ref()?;
+    let channels : Vec<usize> =
+        (0..wfs.nrows())
+            .filter(|&ch| wfs.row(ch).iter().any(|&q| q != 0.0))
+            .collect();
+    if channels.is_empty() { return None }
+
+    let mut fields : Vec<ArrayRef> = Vec::new();
+    fields.push(Arc::new(UInt32Array::from(vec![e.number as u32; channels.len()])));
+    fields.push(Arc::new(UInt32Array::from(channels.iter().map(|&ch| ch as u32).collect::<Vec<_>>())));
+    for i in 0..wfs.ncols() {
+        let column : Vec<f32> = channels.iter().map(|&ch| wfs[(ch, i)] as f32).collect();
+        fields.push(Arc::new(Float32Array::from(column)));
+    }
+    Some(RecordBatch::try_new(s, fields).unwrap())
+}
+
+pub fn get_writer(filename: &str, conf: &SimConfig, layout: &SipmLayout) -> io::Result<EventWriter> {
+    let     n_fine = conf.detailed.as_ref().map_or(0, |d| d.n_bins);
+    let     n_wire = conf.geometry.wire_plane.n_wires;
+    let     n_adc  = if conf.wire_readout().is_some() { n_wire } else { 0 };
+    let     schema = generate_schema(n_wire, n_adc, layout.shape(), n_fine);
+    let mut writer = FeatherFile::for_run(filename, &schema, conf)?;
+    Ok(Box::new( move |e: &Event| {
+        writer.count_event();
         let rb = create_record_batch(e, schema.clone());
-        let ok = writer.write(&rb).unwrap();
-        Ok(ok)
-    })
+        writer.write(&rb);
+        Ok(())
+    }))
+}
+
+pub fn get_waveform_writer(filename: &str, conf: &SimConfig) -> io::Result<EventWriter> {
+    let     n_samples = conf.timing.as_ref().map_or(0, |t| t.n_samples);
+    let     schema    = generate_waveform_schema(n_samples);
+    let mut writer    = FeatherFile::for_run(filename, &schema, conf)?;
+    Ok(Box::new( move |e: &Event| {
+        writer.count_event();
+        if let Some(rb) = create_waveform_batch(e, schema.clone()) {
+            writer.write(&rb);
+        }
+        Ok(())
+    }))
+}
+
+pub fn generate_photon_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new(   "event", DataType::UInt32 , false),
+        Field::new(       "x", DataType::Float32, false),
+        Field::new(       "y", DataType::Float32, false),
+        Field::new(    "time", DataType::Float32, false),
+        Field::new(      "x0", DataType::Float32, false),
+        Field::new(      "y0", DataType::Float32, false),
+        Field::new(      "z0", DataType::Float32, false),
+        Field::new(    "wire", DataType::UInt32 , false),
+        Field::new("shadowed", DataType::Boolean, false),
+        Field::new(    "sipm", DataType::Int32  , false), // -1 if lost
+    ]))
+}
+
+fn create_photon_batch(event: usize, photons: &[PhotonRecord], s: Arc<Schema>) -> RecordBatch {
+    let f32_column = |f: &dyn Fn(&PhotonRecord) -> f64| -> ArrayRef {
+        Arc::new(Float32Array::from(photons.iter().map(|p| f(p) as f32).collect::<Vec<_>>()))
+    };
+    let fields : Vec<ArrayRef> = vec![
+        Arc::new(UInt32Array::from(vec![event as u32; photons.len()])),
+        f32_column(&|p| p.photon.pos.x),
+        f32_column(&|p| p.photon.pos.y),
+        f32_column(&|p| p.photon.time),
+        f32_column(&|p| p.photon.origin.x),
+        f32_column(&|p| p.photon.origin.y),
+        f32_column(&|p| p.photon.origin.z),
+        Arc::new( UInt32Array::from(photons.iter().map(|p| p.wire as u32            ).collect::<Vec<_>>())),
+        Arc::new(BooleanArray::from(photons.iter().map(|p| p.photon.shadowed        ).collect::<Vec<_>>())),
+        Arc::new(  Int32Array::from(photons.iter().map(|p| p.sipm.map_or(-1, |s| s as i32)).collect::<Vec<_>>())),
+    ];
+    RecordBatch::try_new(s, fields).unwrap()
+}
+
+pub fn get_photon_writer(filename: &str, conf: &SimConfig) -> io::Result<PhotonWriter> {
+    let     schema = generate_photon_schema();
+    let mut writer = FeatherFile::for_run(filename, &schema, conf)?;
+    Ok(Box::new( move |event: usize, photons: &[PhotonRecord]| {
+        writer.count_event();
+        if !photons.is_empty() {
+            writer.write(&create_photon_batch(event, photons, schema.clone()));
+        }
+        Ok(())
+    }))
+}
+
+
+/// Writes a lookup table, one row per node with its position and the mean
+/// and variance of each sensor, row-major.
+pub fn write_lut(filename: &str, lut: &Lut) -> io::Result<()> {
+    let (rows, cols) = lut.shape;
+    let mut fields = vec![
+        Field::new("x", DataType::Float64, false),
+        Field::new("y", DataType::Float64, false),
+    ];
+    for stat in ["mean", "var"] {
+        for i in 0..rows {
+            for j in 0..cols {
+                fields.push(Field::new(format!("{stat}_{i}_{j}"), DataType::Float64, false));
+            }
+        }
+    }
+    let mut metadata = lut.provenance.clone();
+    metadata.insert("photons_per_event".to_owned(), lut.photons_per_event.to_string());
+    let schema   = Arc::new(Schema::new_with_metadata(fields, metadata));
+
+    let nodes = lut.ys.iter().flat_map(|&y| lut.xs.iter().map(move |&x| (x, y)));
+    let mut columns : Vec<ArrayRef> = vec![
+        Arc::new(Float64Array::from(nodes.clone().map(|(x, _)| x).collect::<Vec<_>>())),
+        Arc::new(Float64Array::from(nodes        .map(|(_, y)| y).collect::<Vec<_>>())),
+    ];
+    for stat in [&lut.mean, &lut.var] {
+        for i in 0..rows {
+            for j in 0..cols {
+                columns.push(Arc::new(Float64Array::from(stat.iter().map(|m| m[(i, j)]).collect::<Vec<_>>())));
+            }
+        }
+    }
+    let failed     = |e: arrow::error::ArrowError| io::Error::other(format!("{filename}: {e}"));
+    let rb         = RecordBatch::try_new(schema.clone(), columns).map_err(failed)?;
+    let mut writer = FileWriter::try_new(File::create(filename)?, &schema).map_err(failed)?;
+    writer.write(&rb).map_err(failed)?;
+    writer.finish().map_err(failed)
+}
+
+/// Reads a table written by `get_writer` (or `write_lut`), with every column
+/// as f64. The metadata joins those of the schema and the footer.
+pub fn read_table(filename: &str) -> io::Result<Table> {
+    let invalid  = |e: arrow::error::ArrowError| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}: {e}"));
+    let reader   = FileReader::try_new(File::open(filename)?, None).map_err(invalid)?;
+    let schema   = reader.schema();
+    let names    = schema.fields().iter().map(|f| f.name().clone()).collect();
+    let mut metadata = schema.metadata().clone();
+    metadata.extend(reader.custom_metadata().clone());
+    let mut rows = Vec::new();
+    for batch in reader {
+        let batch   = batch.map_err(invalid)?;
+        let columns = batch.columns()
+                           .iter()
+                           .map(|c| cast(c, &DataType::Float64))
+                           .collect::<Result<Vec<_>, _>>()
+                           .map_err(invalid)?;
+        for i in 0..batch.num_rows() {
+            rows.push(columns.iter().map(|c| c.as_primitive::<Float64Type>().value(i)).collect());
+        }
+    }
+    Ok(Table{names, rows, metadata})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use arrow::datatypes::{Int32Type, UInt32Type};
+    use nalgebra::point;
+    use tempfile::NamedTempFile;
+
+    use crate::{Event, Photon};
+    use nalgebra::DMatrix;
+
+    fn test_record(x: f64, sipm: Option<usize>) -> PhotonRecord {
+        let photon = Photon{pos: point!(x, 2.0), time: 3.0, origin: point!(4.0, 5.0, -6.0), shadowed: sipm.is_none()};
+        PhotonRecord{photon, wire: 7, sipm}
+    }
+
+    #[test]
+    fn image_columns_row_major() {
+        let e = Event{
+            number: 1,
+            position: point!(0.0, 0.0),
+            n_electrons: 0,
+            n_photons: 0,
+            n_detected: 0,
+            wire_q: vec![],
+            wire_adc: None,
+            img: DMatrix::from_row_slice(2, 3, &[0, 1, 2, 10, 11, 12]),
+            fine_img: None,
+            waveforms: None,
+        };
+        let schema = generate_schema(0, 0, (2, 3), 0);
+        let rb     = create_record_batch(&e, schema);
+        for (i, j, expected) in [(0, 1, 1), (1, 0, 10), (1, 2, 12)] {
+            let column = rb.column_by_name(&format!("img_{i}_{j}")).unwrap();
+            assert_eq!(column.as_primitive::<UInt32Type>().value(0), expected);
+        }
+    }
+
+    #[test]
+    fn photons_roundtrip() {
+        let file     = NamedTempFile::new().unwrap();
+        let filename = file.path().to_str().unwrap();
+        let conf = SimConfig::new("conf/test.toml").unwrap();
+        {
+            let mut write = get_photon_writer(filename, &conf).unwrap();
+            write(0, &[test_record(1.0, Some(12)), test_record(1.5, None)]).unwrap();
+            write(1, &[]).unwrap();
+            write(2, &[test_record(-1.0, Some(3))]).unwrap();
+        }
+
+        let reader  = FileReader::try_new(File::open(filename).unwrap(), None).unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].num_rows(), 2);
+
+        let events : Vec<u32> = batches.iter().flat_map(|b| b.column(0).as_primitive::<UInt32Type>().values().to_vec()).collect();
+        let sipms  : Vec<i32> = batches.iter().flat_map(|b| b.column(9).as_primitive::< Int32Type>().values().to_vec()).collect();
+        assert_eq!(events, vec![0, 0, 2]);
+        assert_eq!(sipms , vec![12, -1, 3]);
+
+        let table = read_table(filename).unwrap();
+        assert_eq!(table.metadata["n_events"], "3");
+        assert_eq!(table.metadata["config"  ], toml::to_string(&conf).unwrap());
+        assert!(table.metadata.contains_key("git_hash"));
+        assert!(table.metadata.contains_key("end"));
+    }
 }
@@ -2,7 +2,17 @@ mod csv;
 mod feather;
 mod select;
 mod conf;
+pub(crate) mod read;
+
+use std::io;
+use crate::{Event, PhotonRecord};
 
 pub use csv::write_img_1d;
 pub use conf::write_conf;
-pub use select::{Writer, writer};
+pub use select::{Writer, writer, waveform_writer};
+pub use feather::get_photon_writer as photon_writer;
+pub use feather::write_lut;
+pub use read::{Table, read_table, read_events};
+
+pub type EventWriter  = Box<dyn FnMut(&Event) -> io::Result<()>>;
+pub type PhotonWriter = Box<dyn FnMut(usize, &[PhotonRecord]) -> io::Result<()>>;
@@ -0,0 +1,207 @@
+use std::io;
+use std::collections::HashMap;
+use nalgebra::{point, DMatrix};
+
+use crate::Event;
+use crate::io::Writer;
+use crate::io::csv    ::read_table as     csv_table;
+use crate::io::feather::read_table as feather_table;
+
+/// Column names and values of a table, one entry per row, and the file
+/// metadata, such as the provenance of the run.
+pub struct Table {
+    pub names   : Vec<String>,
+    pub rows    : Vec<Vec<f64>>,
+    pub metadata: HashMap<String, String>,
+}
+
+/// Column of each event field. Images are stored row-major.
+struct Columns {
+    event: usize,
+    x    : usize,
+    y    : usize,
+    truth: [Option<usize>; 3], // n_e, n_ph, n_det, missing in older files
+    wires: Vec<usize>,
+    adc  : Vec<usize>,
+    img  : ((usize, usize), Vec<usize>),
+    fine : ((usize, usize), Vec<usize>),
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Columns named `{prefix}{i}`, sorted by `i`.
+fn indexed(names: &[String], prefix: &str) -> Vec<usize> {
+    let mut cols : Vec<(usize, usize)> =
+        names.iter()
+             .enumerate()
+             .filter_map(|(k, n)| n.strip_prefix(prefix)?.parse().ok().map(|i| (i, k)))
+             .collect();
+    cols.sort_unstable();
+    cols.into_iter().map(|(_, k)| k).collect()
+}
+
+/// Columns named `{prefix}{i}_{j}`, sorted row-major, and the image shape.
+pub(crate) fn indexed_2d(names: &[String], prefix: &str) -> io::Result<((usize, usize), Vec<usize>)> {
+    let mut cols : Vec<((usize, usize), usize)> =
+        names.iter()
+             .enumerate()
+             .filter_map(|(k, n)| {
+                 let (i, j) = n.strip_prefix(prefix)?.split_once('_')?;
+                 Some(((i.parse().ok()?, j.parse().ok()?), k))
+             })
+             .collect();
+    cols.sort_unstable();
+    let shape = cols.last().map_or((0, 0), |&((i, j), _)| (i + 1, j + 1));
+    if cols.len() != shape.0 * shape.1 {
+        return Err(invalid(format!("incomplete {prefix}* columns")))
+    }
+    Ok((shape, cols.into_iter().map(|(_, k)| k).collect()))
+}
+
+impl Columns {
+    fn new(names: &[String]) -> io::Result<Self> {
+        let find = |candidates: &[&str]| {
+            names.iter()
+                 .position(|n| candidates.contains(&n.as_str()))
+                 .ok_or_else(|| invalid(format!("missing column {}", candidates[0])))
+        };
+        let wires = indexed(names, "w_");
+        let wires = if wires.is_empty() { indexed(names, "wire_") } else { wires };
+        Ok(Self{ event: find(&["event"])?
+               , x    : find(&["x0", "x"])?
+               , y    : find(&["y0", "y"])?
+               , truth: ["n_e", "n_ph", "n_det"].map(|name| names.iter().position(|n| n == name))
+               , wires
+               , adc  : indexed(names, "adc_")
+               , img  : indexed_2d(names, "img_")?
+               , fine : indexed_2d(names, "fine_")?
+               })
+    }
+
+    fn event(&self, row: &[f64]) -> Event {
+        let counts = |cols: &[usize]| cols.iter().map(|&k| row[k] as usize).collect::<Vec<_>>();
+        let image  = |((rows, cols), ks): &((usize, usize), Vec<usize>)|
+            DMatrix::from_row_slice(*rows, *cols, &counts(ks));
+        let truth  = |k: usize| self.truth[k].map_or(0, |col| row[col] as usize);
+        Event{ number     : row[self.event] as usize
+             , position   : point!(row[self.x], row[self.y])
+             , n_electrons: truth(0)
+             , n_photons  : truth(1)
+             , n_detected : truth(2)
+             , wire_q     : counts(&self.wires)
+             , wire_adc   : (!self.adc.is_empty()).then(|| counts(&self.adc))
+             , img        : image(&self.img)
+             , fine_img   : (!self.fine.1.is_empty()).then(|| image(&self.fine))
+             , waveforms  : None
+             }
+    }
+}
+
+impl Writer {
+    /// Format of a file, from its extension.
+    pub fn for_file(filename: &str) -> Option<Self> {
+        match filename.rsplit_once('.')?.1 {
+            "csv"     => Some(Self::Csv),
+            "feather" => Some(Self::Feather),
+            _         => None,
+        }
+    }
+}
+
+pub fn read_table(filename: &str, format: Writer) -> io::Result<Table> {
+    match format {
+        Writer::Csv     =>     csv_table(filename),
+        Writer::Feather => feather_table(filename),
+    }
+}
+
+/// Reads back the events written by `writer`. Waveforms are not included.
+pub fn read_events(filename: &str) -> io::Result<Vec<Event>> {
+    let format  = Writer::for_file(filename)
+                         .ok_or_else(|| invalid(format!("{filename}: unknown format")))?;
+    let table   = read_table(filename, format)?;
+    let columns = Columns::new(&table.names).map_err(|e| invalid(format!("{filename}: {e}")))?;
+    Ok(table.rows.iter().map(|row| columns.event(row)).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::Builder;
+    use crate::io::writer;
+    use crate::SimConfig;
+
+    fn test_event(number: usize, fine: bool) -> Event {
+        Event{ number
+             , position   : point!(1.5, -2.25)
+             , n_electrons: 100 + number
+             , n_photons  : 2000
+             , n_detected : 300
+             , wire_q     : (0..14).map(|w| w * number).collect()
+             , wire_adc   : None
+             , img        : DMatrix::from_fn(10, 10, |i, j| 10 * i + j + number)
+             , fine_img   : fine.then(|| DMatrix::from_fn(3, 3, |i, j| 3 * i + j))
+             , waveforms  : None
+             }
+    }
+
+    fn roundtrip(extension: &str) {
+        let conf     = SimConfig::new("conf/test.toml").unwrap();
+        let conf     = SimConfig{detailed: Some(crate::Detailed::new(3, 1.0)), ..conf};
+        let file     = Builder::new().suffix(extension).tempfile().unwrap();
+        let filename = file.path().to_str().unwrap();
+        {
+            let layout    = conf.geometry.sipm_plane.layout().unwrap();
+            let mut write = writer(filename, Writer::for_file(filename).unwrap(), &conf, &layout).unwrap();
+            write(&test_event(0, true)).unwrap();
+            write(&test_event(1, true)).unwrap();
+        }
+        let events = read_events(filename).unwrap();
+        assert_eq!(events.len(), 2);
+        for (got, expected) in events.iter().zip([test_event(0, true), test_event(1, true)]) {
+            assert_eq!(got.number  , expected.number);
+            assert_eq!(got.position, expected.position);
+            assert_eq!((got.n_electrons, got.n_photons, got.n_detected), (expected.n_electrons, expected.n_photons, expected.n_detected));
+            assert_eq!(got.wire_q  , expected.wire_q);
+            assert_eq!(got.wire_adc, expected.wire_adc);
+            assert_eq!(got.img     , expected.img);
+            assert_eq!(got.fine_img, expected.fine_img);
+        }
+
+        let table = read_table(filename, Writer::for_file(filename).unwrap()).unwrap();
+        assert_eq!(table.metadata["n_events"], "2");
+        assert_eq!(table.metadata["seed"    ], crate::random::current_seed().to_string());
+        assert_eq!(table.metadata["config"  ], toml::to_string(&conf).unwrap());
+        for key in ["toymc_version", "git_hash", "start", "end"] {
+            assert!(table.metadata.contains_key(key), "missing {key}");
+        }
+    }
+
+    #[test]
+    fn csv_roundtrip() {
+        roundtrip(".csv");
+    }
+
+    #[test]
+    fn feather_roundtrip() {
+        roundtrip(".feather");
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Writer::for_file("out/images.csv"    ), Some(Writer::Csv));
+        assert_eq!(Writer::for_file("out/images.feather"), Some(Writer::Feather));
+        assert_eq!(Writer::for_file("out/images.h5"     ), None);
+        assert_eq!(Writer::for_file("images"            ), None);
+    }
+
+    #[test]
+    fn incomplete_image() {
+        let names : Vec<String> = ["event", "x0", "y0", "img_0_0", "img_1_1"].map(String::from).to_vec();
+        assert!(Columns::new(&names).is_err());
+    }
+}
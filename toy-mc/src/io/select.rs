@@ -1,9 +1,13 @@
-use std::io;
 use clap::ValueEnum;
 
-use crate::{Event, SimConfig};
+use std::io;
+
+use crate::{SimConfig, SipmLayout};
+use crate::io::EventWriter;
 use crate::io::csv    ::get_writer as     csv_writer;
 use crate::io::feather::get_writer as feather_writer;
+use crate::io::csv    ::get_waveform_writer as     csv_waveform_writer;
+use crate::io::feather::get_waveform_writer as feather_waveform_writer;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum Writer {
@@ -11,9 +15,17 @@ pub enum Writer {
     Feather,
 }
 
-pub fn writer(filename: &str, format: Writer, conf: &SimConfig) -> Box<dyn FnMut(&Event) -> io::Result<()>> {
+/// Writer of the events of a run, with images of the sensors in `layout`.
+pub fn writer(filename: &str, format: Writer, conf: &SimConfig, layout: &SipmLayout) -> io::Result<EventWriter> {
+    match format {
+        Writer::Csv     =>     csv_writer(filename, conf, layout),
+        Writer::Feather => feather_writer(filename, conf, layout),
+    }
+}
+
+pub fn waveform_writer(filename: &str, format: Writer, conf: &SimConfig) -> io::Result<EventWriter> {
     match format {
-        Writer::Csv     =>     csv_writer(filename, conf),
-        Writer::Feather => feather_writer(filename, conf),
+        Writer::Csv     =>     csv_waveform_writer(filename, conf),
+        Writer::Feather => feather_waveform_writer(filename, conf),
     }
 }
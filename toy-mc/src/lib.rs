@@ -1,21 +1,49 @@
 mod sipm_plane;
+mod sipm_layout;
 mod wire_plane;
 mod el_gap;
+mod mesh;
 mod geometry;
 mod config;
 mod sim_params;
 mod image;
 mod event;
+mod photon;
+mod timing;
+mod detailed;
+mod calibration;
+mod wire_readout;
+mod simulator;
+mod lut;
+mod provenance;
+mod sweep;
+mod units;
+mod gas;
+mod migrate;
 
 pub mod random;
 pub mod simulation;
+pub mod reco;
+pub mod analysis;
 pub mod io;
 
 pub use sipm_plane::SipmPlane;
+pub use sipm_layout::{SipmLayout, SensorList, Sensor};
 pub use wire_plane::WirePlane;
 pub use el_gap::ElGap;
+pub use mesh::Mesh;
 pub use geometry::Geometry;
 pub use config::SimConfig;
 pub use sim_params::SimParams;
 pub use image::Image;
 pub use event::Event;
+pub use photon::{Photon, PhotonRecord};
+pub use timing::Timing;
+pub use detailed::Detailed;
+pub use calibration::{Calibration, Calibrator, ChannelMap, ChannelCalib};
+pub use wire_readout::WireReadout;
+pub use simulator::Simulator;
+pub use lut::Lut;
+pub use provenance::Provenance;
+pub use sweep::{Sweep, SweepMode, Parameter, Range, Scalar, Point};
+pub use gas::{Gas, Species, Composition};
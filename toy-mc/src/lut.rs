@@ -0,0 +1,231 @@
+use std::io;
+use std::collections::HashMap;
+use nalgebra::{point, DMatrix, Point2};
+
+use crate::{Simulator, Provenance};
+use crate::io::{read_table, Writer, write_lut};
+use crate::io::read::indexed_2d;
+use crate::reco::Response;
+
+/// Light response lookup table: mean and variance of the signal of each
+/// sensor for events at the nodes of a square grid in the SiPM frame.
+/// Values between nodes are interpolated bilinearly, positions outside the
+/// grid use the closest edge.
+#[derive(Debug, Clone)]
+pub struct Lut {
+    pub(crate) xs               : Vec<f64>,
+    pub(crate) ys               : Vec<f64>,
+    pub(crate) shape            : (usize, usize),
+    pub(crate) mean             : Vec<DMatrix<f64>>, // by node, iy * nx + ix
+    pub(crate) var              : Vec<DMatrix<f64>>,
+    pub(crate) photons_per_event: f64,
+    pub(crate) provenance       : HashMap<String, String>, // of the run that built it
+}
+
+/// Index of the lower node and the fractional distance to the next one.
+fn bracket(nodes: &[f64], u: f64) -> (usize, f64) {
+    let n = nodes.len();
+    if n == 1 { return (0, 0.0) }
+    let k = nodes.partition_point(|&v| v <= u).clamp(1, n - 1) - 1;
+    let t = ((u - nodes[k]) / (nodes[k+1] - nodes[k])).clamp(0.0, 1.0);
+    (k, t)
+}
+
+impl Lut {
+    /// Simulates `n_events` events at each of the `n_nodes` x `n_nodes`
+    /// nodes spanning [-`extent`, `extent`] along both axes.
+    pub fn build(sim: &mut Simulator, n_nodes: usize, extent: f64, n_events: usize) -> Self {
+        let step  = if n_nodes > 1 { 2.0 * extent / (n_nodes - 1) as f64 } else { 0.0 };
+        let xs    : Vec<f64> = (0..n_nodes).map(|i| -extent + i as f64 * step).collect();
+        let ys    = xs.clone();
+        let shape = sim.layout().shape();
+        let to_wires = sim.rotation().inverse();
+        let run      = Provenance::new(sim.conf());
+
+        let mut mean = Vec::with_capacity(n_nodes * n_nodes);
+        let mut var  = Vec::with_capacity(n_nodes * n_nodes);
+        for &y in &ys {
+            for &x in &xs {
+                let position   = to_wires * point!(x, y);
+                let mut sum    = DMatrix::<f64>::zeros(shape.0, shape.1);
+                let mut sum_sq = DMatrix::<f64>::zeros(shape.0, shape.1);
+                for i in 0..n_events {
+                    let img = sim.simulate_at(i, position, None).img.map(|q| q as f64);
+                    sum_sq += img.component_mul(&img);
+                    sum    += img;
+                }
+                let n = n_events as f64;
+                let m = sum / n;
+                let v = (sum_sq - m.component_mul(&m) * n) / (n - 1.0).max(1.0);
+                mean.push(m);
+                var .push(v);
+            }
+        }
+        let photons_per_event = sim.conf().sim_params.n_photons_ave();
+        let provenance        = run.header().into_iter()
+                                   .chain(Provenance::footer(n_nodes * n_nodes * n_events))
+                                   .collect();
+        Self{xs, ys, shape, mean, var, photons_per_event, provenance}
+    }
+
+    fn interpolate(&self, values: &[DMatrix<f64>], x: f64, y: f64) -> DMatrix<f64> {
+        let nx       = self.xs.len();
+        let (ix, tx) = bracket(&self.xs, x);
+        let (iy, ty) = bracket(&self.ys, y);
+        let ix1      = (ix + 1).min(nx - 1);
+        let iy1      = (iy + 1).min(self.ys.len() - 1);
+        let node     = |i: usize, j: usize| &values[j * nx + i];
+        node(ix , iy ) * ((1.0 - tx) * (1.0 - ty))
+      + node(ix1, iy ) * (       tx  * (1.0 - ty))
+      + node(ix , iy1) * ((1.0 - tx) *        ty )
+      + node(ix1, iy1) * (       tx  *        ty )
+    }
+
+    /// Mean signal of each sensor for an event at (`x`, `y`).
+    pub fn expected(&self, x: f64, y: f64) -> DMatrix<f64> {
+        self.interpolate(&self.mean, x, y)
+    }
+
+    /// Variance of the signal of each sensor for an event at (`x`, `y`).
+    pub fn variance(&self, x: f64, y: f64) -> DMatrix<f64> {
+        self.interpolate(&self.var, x, y)
+    }
+
+    /// Mean number of photons emitted towards the SiPM plane per event in
+    /// the simulation that built the table.
+    pub fn photons_per_event(&self) -> f64 {
+        self.photons_per_event
+    }
+
+    /// Provenance of the run that built the table.
+    pub fn provenance(&self) -> &HashMap<String, String> {
+        &self.provenance
+    }
+
+    pub fn write(&self, filename: &str) -> io::Result<()> {
+        write_lut(filename, self)
+    }
+
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}: {msg}"));
+        let table   = read_table(filename, Writer::Feather)?;
+        let column  = |name: &str| table.names.iter().position(|n| n == name).ok_or_else(|| invalid(&format!("missing column {name}")));
+        let (ix, iy) = (column("x")?, column("y")?);
+        let (shape, mean_cols) = indexed_2d(&table.names, "mean_")?;
+        let (_    ,  var_cols) = indexed_2d(&table.names, "var_" )?;
+        let photons_per_event  = table.metadata.get("photons_per_event")
+                                      .and_then(|v| v.parse().ok())
+                                      .ok_or_else(|| invalid("missing photons_per_event"))?;
+
+        // nodes are written row by row, x running fastest
+        let mut xs : Vec<f64> = table.rows.iter().map(|r| r[ix]).collect();
+        let mut ys : Vec<f64> = table.rows.iter().map(|r| r[iy]).collect();
+        xs.sort_by(f64::total_cmp); xs.dedup();
+        ys.sort_by(f64::total_cmp); ys.dedup();
+        if xs.len() * ys.len() != table.rows.len() {
+            return Err(invalid("nodes do not form a grid"))
+        }
+        let matrices = |cols: &[usize]| -> Vec<DMatrix<f64>> {
+            table.rows.iter()
+                 .map(|r| DMatrix::from_row_iterator(shape.0, shape.1, cols.iter().map(|&k| r[k])))
+                 .collect()
+        };
+        Ok(Self{ xs, ys, shape
+               , mean: matrices(&mean_cols)
+               , var : matrices( &var_cols)
+               , photons_per_event
+               , provenance: table.metadata.into_iter().filter(|(k, _)| k != "photons_per_event").collect()
+               })
+    }
+}
+
+/// Fraction of the emitted photons reaching each sensor.
+impl Response for Lut {
+    fn expected(&self, p: &Point2<f64>) -> DMatrix<f64> {
+        Lut::expected(self, p.x, p.y) / self.photons_per_event
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use float_eq::assert_float_eq;
+    use tempfile::Builder;
+    use crate::SimConfig;
+
+    /// 3 x 3 nodes every 1.0, 1 x 2 sensors, with mean x + 10 y (+ 100 for
+    /// the second sensor).
+    fn linear_lut() -> Lut {
+        let xs   = vec![-1.0, 0.0, 1.0];
+        let ys   = xs.clone();
+        let mean = ys.iter()
+                     .flat_map(|&y| xs.iter().map(move |&x| DMatrix::from_row_slice(1, 2, &[x + 10.0 * y, x + 10.0 * y + 100.0])))
+                     .collect::<Vec<_>>();
+        let var  = mean.clone();
+        Lut{xs, ys, shape: (1, 2), mean, var, photons_per_event: 2.0
+           , provenance: HashMap::from([("seed".to_owned(), "7".to_owned())])}
+    }
+
+    #[test]
+    fn bilinear_interpolation() {
+        let lut = linear_lut();
+        let m   = lut.expected(0.25, -0.5);
+        assert_float_eq!(m[(0, 0)], 0.25 - 5.0  , abs<=1e-12);
+        assert_float_eq!(m[(0, 1)], 0.25 + 95.0 , abs<=1e-12);
+        let m   = lut.expected(1.0, 1.0);
+        assert_float_eq!(m[(0, 0)], 11.0, abs<=1e-12);
+    }
+
+    #[test]
+    fn clamped_outside() {
+        let lut = linear_lut();
+        assert_eq!(lut.expected(5.0, -3.0), lut.expected(1.0, -1.0));
+    }
+
+    #[test]
+    fn response_is_a_fraction() {
+        let lut = linear_lut();
+        assert_float_eq!(Response::expected(&lut, &point!(0.0, 0.0))[(0, 1)], 50.0, abs<=1e-12);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let lut      = linear_lut();
+        let file     = Builder::new().suffix(".feather").tempfile().unwrap();
+        let filename = file.path().to_str().unwrap();
+        lut.write(filename).unwrap();
+        let read     = Lut::from_file(filename).unwrap();
+        assert_eq!(read.xs   , lut.xs);
+        assert_eq!(read.ys   , lut.ys);
+        assert_eq!(read.shape, lut.shape);
+        assert_eq!(read.mean , lut.mean);
+        assert_eq!(read.var  , lut.var);
+        assert_eq!(read.photons_per_event, 2.0);
+        assert_eq!(read.provenance, lut.provenance);
+    }
+
+    #[test]
+    fn unwritable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = linear_lut().write(dir.path().join("missing/lut.feather").to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn build_small() {
+        let conf    = SimConfig::new("conf/test.toml").unwrap();
+        let mut sim = Simulator::new(&conf).unwrap();
+        let lut     = Lut::build(&mut sim, 2, 20.0, 2);
+        assert_eq!(lut.mean.len(), 4);
+        assert_eq!(lut.shape, sim.layout().shape());
+        assert!(lut.var.iter().all(|v| v.iter().all(|&x| x >= -1e-9)));
+
+        // the brightest sensor is next to the node
+        let m   = lut.expected(-20.0, 20.0).transpose();
+        let ch  = (0..m.len()).max_by(|&a, &b| m[a].total_cmp(&m[b])).unwrap();
+        let pos = sim.layout().positions()[ch];
+        assert!((pos - point!(-20.0, 20.0)).norm() < 7.0, "{pos}");
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use derive_new::new;
+
+use crate::units;
+
+/// Square mesh (or window) parallel to the wire plane, placed between the
+/// wires and the SiPM plane. The grid lines are aligned with the wire frame.
+/// A mesh with zero thickness acts as a plain window with the given
+/// transparency.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Mesh {
+    /// Distance from the wire plane, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
+    pub z           : f64,
+    /// Distance between grid lines, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
+    pub pitch       : f64,
+    /// Width of the grid lines, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
+    pub thickness   : f64,
+    /// Probability of crossing the mesh between grid lines
+    #[serde(default = "full_transparency")]
+    pub transparency: f64,
+}
+
+fn full_transparency() -> f64 { 1.0 }
+
+impl Mesh {
+    /// Whether a photon crossing the mesh plane at (`x`, `y`) falls on one of
+    /// the grid lines. The mesh is treated as infinitely thin along z.
+    pub fn hits_grid(&self, x: f64, y: f64) -> bool {
+        if self.thickness <= 0.0 { return false }
+        let half = self.thickness / 2.0;
+        let on_line = |u: f64| {
+            let d = u.rem_euclid(self.pitch);
+            d < half || d > self.pitch - half
+        };
+        on_line(x) || on_line(y)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_mesh() -> Mesh {
+        Mesh::new(2.0, 1.0, 0.1, 1.0)
+    }
+
+    #[test]
+    fn grid_lines() {
+        let mesh = test_mesh();
+        assert!( mesh.hits_grid( 0.0 ,  0.5 ));
+        assert!( mesh.hits_grid( 0.5 , -3.02));
+        assert!( mesh.hits_grid( 2.96,  0.5 ));
+        assert!(!mesh.hits_grid( 0.5 ,  0.5 ));
+        assert!(!mesh.hits_grid(-0.3 ,  1.2 ));
+    }
+
+    #[test]
+    fn window_never_hits() {
+        let mesh = Mesh::new(2.0, 1.0, 0.0, 0.9);
+        for i in 0..100 {
+            let u = i as f64 * 0.01;
+            assert!(!mesh.hits_grid(u, u));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use toml_edit::{DocumentMut, Item, Value};
+
+/// Layout of the configuration files read and written by this version.
+/// Files without `version` are version 1.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// Upgrade from version `i + 1` to `i + 2`, returning a notice for each
+/// change.
+const MIGRATIONS: [fn(&mut DocumentMut) -> Vec<String>; 1] = [
+    rotation_in_degrees,
+];
+
+/// Upgrades a configuration written for an older version in place, keeping
+/// its comments, and returns a notice for each change. A configuration
+/// without `version` is taken as `implied`.
+pub fn migrate(doc: &mut DocumentMut, implied: i64) -> Result<Vec<String>, String> {
+    let item    = doc.get("version").map(|item| (item.as_integer(), item.to_string()));
+    let (version, text) = item.unwrap_or((Some(implied), implied.to_string()));
+    let version = version.filter(|v| (1..=CONFIG_VERSION).contains(v))
+                         .ok_or_else(|| format!("unsupported version {}, expected at most {CONFIG_VERSION}", text.trim()))?;
+    let notices = MIGRATIONS[(version - 1) as usize..].iter().flat_map(|m| m(doc)).collect();
+    if version < CONFIG_VERSION {
+        doc["version"] = toml_edit::value(CONFIG_VERSION);
+    }
+    Ok(notices)
+}
+
+/// Entry at a dotted path.
+fn entry<'a>(doc: &'a mut DocumentMut, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(doc.as_item_mut(), |item, key| item.get_mut(key))
+        .and_then(Item::as_value_mut)
+}
+
+/// Replaces a value, keeping its comments.
+fn replace(old: &mut Value, new: impl Into<Value>) {
+    let decor = old.decor().clone();
+    *old = new.into();
+    *old.decor_mut() = decor;
+}
+
+/// Version 1 used `wire_rotation` as radians, although it was meant in
+/// degrees. Plain numbers are now degrees, old files keep their geometry.
+fn rotation_in_degrees(doc: &mut DocumentMut) -> Vec<String> {
+    let Some(rotation) = entry(doc, "geometry.wire_plane.wire_rotation") else { return vec![] };
+    let Some(angle)    = rotation.as_float().or(rotation.as_integer().map(|v| v as f64)) else { return vec![] };
+    replace(rotation, format!("{angle} rad"));
+    vec![format!("wire_rotation = {angle} was read as radians before version 2, now \"{angle} rad\"; \
+                  write it in degrees if that was the intent")]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn rotation_as_radians() {
+        let mut doc = "n_events = 1\n\n[geometry.wire_plane]\nwire_rotation = 45 # angle\n".parse::<DocumentMut>().unwrap();
+        let notices = migrate(&mut doc, 1).unwrap();
+        assert_eq!(notices.len(), 1);
+        assert_eq!(doc.to_string(), "n_events = 1\nversion = 2\n\n[geometry.wire_plane]\nwire_rotation = \"45 rad\" # angle\n");
+    }
+
+    #[test]
+    fn current_unchanged() {
+        let contents = "version = 2\n\n[geometry.wire_plane]\nwire_rotation = 45\n";
+        let mut doc  = contents.parse::<DocumentMut>().unwrap();
+        assert!(migrate(&mut doc, 1).unwrap().is_empty());
+        assert_eq!(doc.to_string(), contents);
+
+        // a fragment read along with current files
+        let contents = "[geometry.wire_plane]\nwire_rotation = 45\n";
+        let mut doc  = contents.parse::<DocumentMut>().unwrap();
+        assert!(migrate(&mut doc, CONFIG_VERSION).unwrap().is_empty());
+        assert_eq!(doc.to_string(), contents);
+    }
+
+    #[test]
+    fn unsupported_versions() {
+        for contents in ["version = 3", "version = 0", "version = \"2\""] {
+            let mut doc = contents.parse::<DocumentMut>().unwrap();
+            assert!(migrate(&mut doc, 1).is_err(), "{contents}");
+        }
+    }
+}
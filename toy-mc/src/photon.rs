@@ -0,0 +1,19 @@
+use nalgebra::{Point2, Point3};
+
+/// Photon traced to the SiPM plane. `pos` is where it lands (or would have
+/// landed, if `shadowed`). Times in ns, NaN if `shadowed`.
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub pos     : Point2<f64>,
+    pub time    : f64,
+    pub origin  : Point3<f64>,
+    pub shadowed: bool,
+}
+
+/// Truth record of a photon for the photon-level output.
+#[derive(Debug, Clone, Copy)]
+pub struct PhotonRecord {
+    pub photon: Photon,
+    pub wire  : usize,
+    pub sipm  : Option<usize>,
+}
@@ -0,0 +1,89 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::SimConfig;
+use crate::random::current_seed;
+
+/// Description of the run that produced a file, stored inside the file so
+/// that it remains self-describing when moved away from its `run.conf`.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub version : String,
+    pub git_hash: String,
+    pub seed    : u64,
+    pub start   : String,
+    pub config  : String, // full configuration, TOML
+}
+
+/// UTC time as `YYYY-MM-DDThh:mm:ssZ`.
+pub fn timestamp(t: SystemTime) -> String {
+    let secs        = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Days since the epoch to civil date, from H. Hinnant's date algorithms
+    let z   = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp  = (5 * doy + 2) / 153;
+    let d   = doy - (153 * mp + 2) / 5 + 1;
+    let m   = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y   = yoe + era * 400 + (m <= 2) as i64;
+    format!("{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z", rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+impl Provenance {
+    /// Provenance of a run starting now with `conf`, using the seed of the
+    /// random generator of the current thread.
+    pub fn new(conf: &SimConfig) -> Self {
+        Self{ version : env!("CARGO_PKG_VERSION").to_owned()
+            , git_hash: env!("TOYMC_GIT_HASH").to_owned()
+            , seed    : current_seed()
+            , start   : timestamp(SystemTime::now())
+            , config  : toml::to_string(conf).expect("Could not serialize config")
+            }
+    }
+
+    /// Entries known when the file is created.
+    pub fn header(&self) -> Vec<(String, String)> {
+        vec![ ("toymc_version".to_owned(), self.version .clone())
+            , ("git_hash"     .to_owned(), self.git_hash.clone())
+            , ("seed"         .to_owned(), self.seed.to_string())
+            , ("start"        .to_owned(), self.start   .clone())
+            , ("config"       .to_owned(), self.config  .clone())
+            ]
+    }
+
+    /// Entries written when the file is closed, after `n_events` events.
+    pub fn footer(n_events: usize) -> Vec<(String, String)> {
+        vec![ ("end"     .to_owned(), timestamp(SystemTime::now()))
+            , ("n_events".to_owned(), n_events.to_string())
+            ]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps() {
+        let at = |secs| timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0)            , "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400)  , "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn header_contents() {
+        crate::random::seed(42);
+        let conf   = SimConfig::new("conf/test.toml").unwrap();
+        let header = Provenance::new(&conf).header();
+        let get    = |key: &str| header.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()).unwrap();
+        assert_eq!(get("seed"), "42");
+        assert_eq!(get("toymc_version"), env!("CARGO_PKG_VERSION"));
+        assert_eq!(get("config"), toml::to_string(&conf).unwrap());
+    }
+}
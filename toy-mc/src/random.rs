@@ -1,19 +1,58 @@
+use std::cell::RefCell;
 use std::f64::consts::TAU;
 use nalgebra::{point, Point2};
-use rand::rng;
-use rand_distr::{Poisson, Normal, Uniform, Distribution};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Poisson, Normal, Uniform, Exp, Binomial, Distribution};
+
+
+/// Largest seed, so that it fits in a TOML integer.
+pub const MAX_SEED: u64 = i64::MAX as u64;
+
+thread_local! {
+    // Seeds from the system entropy
+    static RNG: RefCell<(u64, ChaCha8Rng)> = RefCell::new(seeded(rand::random::<u64>() & MAX_SEED));
+}
+
+/// A named generator, unlike `StdRng`, gives the same sequence for a seed
+/// with every version of `rand`.
+fn seeded(seed: u64) -> (u64, ChaCha8Rng) {
+    (seed, ChaCha8Rng::seed_from_u64(seed))
+}
+
+/// Restarts the generator of the current thread from `seed`.
+pub fn seed(seed: u64) {
+    RNG.with_borrow_mut(|rng| *rng = seeded(seed));
+}
+
+/// Seed of the generator of the current thread.
+pub fn current_seed() -> u64 {
+    RNG.with_borrow(|rng| rng.0)
+}
+
+fn sample<T>(dist: impl Distribution<T>) -> T {
+    RNG.with_borrow_mut(|rng| dist.sample(&mut rng.1))
+}
 
 
 pub fn random_in_circle(r: f64) -> Point2<f64> {
+    if r <= 0.0 { return Point2::origin() }
     let r   = uniform(0.0, r.powi(2)).sqrt();
     let phi = uniform(0.0, TAU);
     point!(r * phi.cos(), r * phi.sin())
 }
 
 
-pub fn uniform(low: f64, high:f64 ) -> f64 { Uniform::new(low, high).unwrap().sample(&mut rng()) }
-pub fn poisson(mean: f64          ) -> f64 { Poisson::new(mean     ).unwrap().sample(&mut rng()) }
-pub fn normal (mean: f64, std: f64) -> f64 { Normal ::new(mean, std).unwrap().sample(&mut rng()) }
+pub fn uniform(low: f64, high:f64 ) -> f64 { sample(Uniform::new(low, high).unwrap()) }
+pub fn poisson(mean: f64          ) -> f64 { sample(Poisson::new(mean     ).unwrap()) }
+pub fn normal (mean: f64, std: f64) -> f64 { sample(Normal ::new(mean, std).unwrap()) }
+
+pub fn binomial(n: u64, p: f64) -> u64 { sample(Binomial::new(n, p).unwrap()) }
+
+pub fn exponential(mean: f64) -> f64 {
+    if mean <= 0.0 { return 0.0 }
+    sample(Exp::new(1.0 / mean).unwrap())
+}
 
 
 #[cfg(test)]
@@ -50,6 +89,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn exponential_positive() {
+        for _ in 0..1_000 {
+            assert!(exponential(12.3) >= 0.0);
+        }
+        assert_float_eq!(exponential(0.0), 0.0, ulps<=2);
+    }
+
+    #[test]
+    fn binomial_within_range() {
+        for _ in 0..1_000 {
+            assert!(binomial(12, 0.3) <= 12);
+        }
+        assert_eq!(binomial(12, 1.0), 12);
+        assert_eq!(binomial(12, 0.0),  0);
+    }
+
+    #[test]
+    fn seed_reproducible() {
+        seed(1234);
+        let first : Vec<f64> = (0..10).map(|_| normal(0.0, 1.0)).collect();
+        seed(1234);
+        let again : Vec<f64> = (0..10).map(|_| normal(0.0, 1.0)).collect();
+        assert_eq!(first, again);
+        assert_eq!(current_seed(), 1234);
+    }
+
     #[test]
     fn circle_within_r() {
         let r = 123.4;
@@ -57,5 +123,28 @@ mod tests {
             let p = random_in_circle(r) - Point2::origin();
             assert!(p.magnitude() < r);
         }
+        assert_eq!(random_in_circle(0.0), Point2::origin());
+    }
+
+    #[test]
+    fn circle_radially_uniform() {
+        use crate::analysis::{ks_distance, ks_p_value, bin, chi2, chi2_p_value};
+        seed(4805);
+        // uniform over the area: (r/R)² and φ uniform
+        let r        = 2.5;
+        let points   : Vec<_> = (0..20_000).map(|_| random_in_circle(r)).collect();
+        let r2       : Vec<f64> = points.iter().map(|p| (p - Point2::origin()).norm_squared() / (r * r)).collect();
+        let d        = ks_distance(&r2, |u| u.clamp(0.0, 1.0));
+        assert!(ks_p_value(d, r2.len()) > 1e-3, "KS distance {d}");
+
+        let mut counts = vec![0.0; 16];
+        points.iter().filter_map(|p| bin(p.y.atan2(p.x).rem_euclid(TAU), 0.0, TAU, 16)).for_each(|i| counts[i] += 1.0);
+        let expected = vec![points.len() as f64 / 16.0; 16];
+        let p_value  = chi2_p_value(chi2(&counts, &expected), 15);
+        assert!(p_value > 1e-3, "p-value {p_value}");
+
+        // a linear radial distribution is not uniform over the area
+        let linear   : Vec<f64> = (0..20_000).map(|_| uniform(0.0, 1.0).powi(2)).collect();
+        assert!(ks_p_value(ks_distance(&linear, |u| u.clamp(0.0, 1.0)), linear.len()) < 1e-6);
     }
 }
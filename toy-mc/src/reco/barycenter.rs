@@ -0,0 +1,139 @@
+use nalgebra::{DMatrix, Point2};
+use derive_new::new;
+
+use crate::SipmLayout;
+
+/// Channels used for the reconstruction: those within the `n` x `n` block
+/// centred on `ch` for grids, the `n`² sensors closest to `ch` for sensor
+/// lists. Channels are numbered row-major.
+pub fn window(layout: &SipmLayout, ch: usize, n: usize) -> Vec<usize> {
+    match layout {
+        SipmLayout::Grid{..} => {
+            let (rows, cols) = layout.shape();
+            let (r0, c0)     = (ch / cols, ch % cols);
+            let (lo, hi)     = ((n.max(1) - 1) / 2, n / 2);
+            let rr = r0.saturating_sub(lo) ..= (r0 + hi).min(rows - 1);
+            let cc = c0.saturating_sub(lo) ..= (c0 + hi).min(cols - 1);
+            rr.flat_map(|r| cc.clone().map(move |c| r * cols + c))
+              .collect()
+        }
+        SipmLayout::List(_) => {
+            let positions = layout.positions();
+            let p0        = positions[ch];
+            let distance  = |k: usize| (positions[k] - p0).norm();
+            let mut chs : Vec<usize> = (0..positions.len()).collect();
+            chs.sort_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+            chs.truncate(n * n);
+            chs
+        }
+    }
+}
+
+/// Charge-weighted mean of the sensor positions. Channels with fewer than
+/// `threshold` counts are ignored and, if `window` is set, only the
+/// `window` x `window` sensors around the brightest one are used.
+#[derive(new, Debug, Clone, Copy, Default)]
+pub struct Barycenter {
+    pub threshold: usize,
+    pub window   : Option<usize>,
+}
+
+impl Barycenter {
+    /// Reconstructed position, `None` if no channel passes the selection.
+    pub fn reconstruct(&self, img: &DMatrix<usize>, layout: &SipmLayout) -> Option<Point2<f64>> {
+        let positions = layout.positions();
+        // row-major, like the channel numbers
+        let q : Vec<usize> = img.transpose().iter().copied().collect();
+        let channels = match self.window {
+            Some(n) => {
+                let max = (0..q.len()).max_by_key(|&ch| q[ch])?;
+                window(layout, max, n)
+            }
+            None => (0..q.len()).collect(),
+        };
+
+        let (mut sum, mut norm) = (Point2::origin().coords, 0.0);
+        for ch in channels {
+            if q[ch] < self.threshold || q[ch] == 0 { continue }
+            sum  += positions[ch].coords * q[ch] as f64;
+            norm += q[ch] as f64;
+        }
+        (norm > 0.0).then(|| Point2::from(sum / norm))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use float_eq::assert_float_eq;
+    use crate::{SensorList, Sensor};
+
+    fn grid() -> SipmLayout {
+        // 5 x 5 sensors every 1.0, centred at the origin
+        SipmLayout::grid((5, 5), (1.0, 1.0), (0.0, 0.0), (0.0, 0.0))
+    }
+
+    #[test]
+    fn single_channel() {
+        let mut img = DMatrix::zeros(5, 5);
+        img[(1, 3)] = 10;
+        let p = Barycenter::default().reconstruct(&img, &grid()).unwrap();
+        assert_float_eq!(p.x,  1.0, abs<=1e-12);
+        assert_float_eq!(p.y, -1.0, abs<=1e-12);
+    }
+
+    #[test]
+    fn weighted_mean() {
+        let mut img = DMatrix::zeros(5, 5);
+        img[(2, 2)] = 30;
+        img[(2, 3)] = 10;
+        let p = Barycenter::default().reconstruct(&img, &grid()).unwrap();
+        assert_float_eq!(p.x, 0.25, abs<=1e-12);
+        assert_float_eq!(p.y, 0.0 , abs<=1e-12);
+    }
+
+    #[test]
+    fn threshold_and_window() {
+        let mut img = DMatrix::zeros(5, 5);
+        img[(2, 2)] = 30;
+        img[(2, 3)] = 10;
+        img[(0, 0)] = 5;
+        img[(4, 4)] = 20;
+
+        let all      = Barycenter::new(0, None   ).reconstruct(&img, &grid()).unwrap();
+        let windowed = Barycenter::new(0, Some(3)).reconstruct(&img, &grid()).unwrap();
+        let cut      = Barycenter::new(6, Some(3)).reconstruct(&img, &grid()).unwrap();
+        assert!(all.x > 0.25);
+        assert_float_eq!(windowed.x, 0.25, abs<=1e-12);
+        assert_float_eq!(cut     .x, 0.25, abs<=1e-12);
+        assert!(Barycenter::new(100, None).reconstruct(&img, &grid()).is_none());
+    }
+
+    #[test]
+    fn empty_image() {
+        let img = DMatrix::zeros(5, 5);
+        assert_eq!(Barycenter::new(0, Some(3)).reconstruct(&img, &grid()), None);
+    }
+
+    #[test]
+    fn grid_window_clipped() {
+        let layout = grid();
+        let mut w  = window(&layout, 0, 3);
+        w.sort();
+        assert_eq!(w, vec![0, 1, 5, 6]);
+        assert_eq!(window(&layout, 12, 3).len(), 9);
+        assert_eq!(window(&layout, 12, 1), vec![12]);
+    }
+
+    #[test]
+    fn list_window() {
+        let sensors = [0.0, 1.0, 5.0, 1.5].iter().map(|&x| Sensor{x, y: 0.0, size_x: 0.5, size_y: 0.5}).collect();
+        let layout  = SipmLayout::List(SensorList::new(sensors));
+        assert_eq!(window(&layout, 0, 1), vec![0]);
+        let mut w = window(&layout, 1, 2);
+        w.sort();
+        assert_eq!(w, vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,71 @@
+use nalgebra::{DMatrix, Point2};
+
+use crate::reco::Response;
+
+/// Energy from the total SiPM signal. With a `response`, the signal is
+/// corrected by the light collection at the event position, relative to the
+/// collection at the centre of the plane. Signals are converted to energy
+/// with the scale given by `calibrate`.
+pub struct EnergyEstimator<R: Response> {
+    response : Option<R>,
+    reference: f64,
+}
+
+impl<R: Response> EnergyEstimator<R> {
+    pub fn new(response: Option<R>) -> Self {
+        let reference = response.as_ref().map_or(1.0, |r| r.expected(&Point2::origin()).sum());
+        Self{response, reference}
+    }
+
+    /// Total signal, corrected for the light collection at `position` if the
+    /// estimator has a response.
+    pub fn light(&self, img: &DMatrix<usize>, position: &Point2<f64>) -> f64 {
+        let total = img.sum() as f64;
+        match &self.response {
+            Some(r) => total * self.reference / r.expected(position).sum(),
+            None    => total,
+        }
+    }
+}
+
+/// Scale bringing the mean of `light` to `energy`, as when calibrating with
+/// a known line.
+pub fn calibrate(light: &[f64], energy: f64) -> f64 {
+    energy * light.len() as f64 / light.iter().sum::<f64>()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use nalgebra::point;
+    use crate::SipmLayout;
+    use crate::reco::SolidAngle;
+
+    #[test]
+    fn uncorrected_is_the_sum() {
+        let est = EnergyEstimator::<SolidAngle>::new(None);
+        let img = DMatrix::from_row_slice(1, 3, &[1, 2, 3]);
+        assert_float_eq!(est.light(&img, &point!(10.0, 0.0)), 6.0, ulps<=1);
+    }
+
+    #[test]
+    fn correction_flattens_the_response() {
+        let layout   = SipmLayout::grid((10, 10), (6.0, 6.0), (0.5, 0.5), (0.0, 0.0));
+        let response = SolidAngle::new(&layout, 5.0);
+        let est      = EnergyEstimator::new(Some(response.clone()));
+        let light    = |p: Point2<f64>| {
+            let img = response.expected(&p).map(|f| (1e6 * f).round() as usize);
+            est.light(&img, &p)
+        };
+        let centre = light(point!( 0.0, 0.0));
+        let edge   = light(point!(30.0, 0.0));
+        assert_float_eq!(edge, centre, rmax<=1e-4);
+    }
+
+    #[test]
+    fn calibration() {
+        assert_float_eq!(calibrate(&[90.0, 110.0], 41.5), 0.415, rmax<=1e-12);
+    }
+}
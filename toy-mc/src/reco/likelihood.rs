@@ -0,0 +1,148 @@
+use nalgebra::{DMatrix, Point2};
+
+use crate::SipmLayout;
+use crate::reco::{Barycenter, Response, nelder_mead};
+
+/// Smallest expected count, so that channels with signal where none is
+/// expected do not make the likelihood infinite.
+const MIN_EXPECTED: f64 = 1e-9;
+
+/// Result of a likelihood fit. `deviance` is the Poisson deviance at the
+/// minimum, distributed as a χ² with `ndof` degrees of freedom for a good
+/// fit.
+#[derive(Debug, Clone)]
+pub struct Fit {
+    pub position  : Point2<f64>,
+    pub n_photons : f64,
+    pub nll       : f64,
+    pub deviance  : f64,
+    pub ndof      : usize,
+    pub iterations: usize,
+    pub converged : bool,
+}
+
+/// Maximum-likelihood fit of the position and number of photons of an event,
+/// comparing the image with `response` under Poisson statistics. The fit
+/// starts from the `seed` barycenter.
+pub struct LikelihoodFit<R: Response> {
+    pub response: R,
+    pub seed    : Barycenter,
+    pub tol     : f64,
+    pub max_iter: usize,
+}
+
+impl<R: Response> LikelihoodFit<R> {
+    pub fn new(response: R) -> Self {
+        Self{ response, seed: Barycenter::new(0, Some(3)), tol: 1e-6, max_iter: 1000 }
+    }
+
+    /// Negative log-likelihood, without the constant terms.
+    pub fn nll(&self, img: &DMatrix<usize>, p: &Point2<f64>, n_photons: f64) -> f64 {
+        self.response
+            .expected(p)
+            .iter()
+            .zip(img.iter())
+            .map(|(&f, &k)| {
+                let mu = (n_photons * f).max(MIN_EXPECTED);
+                mu - k as f64 * mu.ln()
+            })
+            .sum()
+    }
+
+    fn deviance(&self, img: &DMatrix<usize>, p: &Point2<f64>, n_photons: f64) -> f64 {
+        self.response
+            .expected(p)
+            .iter()
+            .zip(img.iter())
+            .map(|(&f, &k)| {
+                let mu = (n_photons * f).max(MIN_EXPECTED);
+                let k  = k as f64;
+                let log_term = if k > 0.0 { k * (k / mu).ln() } else { 0.0 };
+                2.0 * (mu - k + log_term)
+            })
+            .sum()
+    }
+
+    /// `None` for images without signal.
+    pub fn fit(&self, img: &DMatrix<usize>, layout: &SipmLayout) -> Option<Fit> {
+        let p0    = self.seed.reconstruct(img, layout)?;
+        let total = img.sum() as f64;
+        let n0    = total / self.response.expected(&p0).sum().max(MIN_EXPECTED);
+
+        let f = |v: &[f64]| {
+            if v[2] <= 0.0 { return f64::INFINITY }
+            self.nll(img, &Point2::new(v[0], v[1]), v[2])
+        };
+        let min      = nelder_mead(f, &[p0.x, p0.y, n0], &[1.0, 1.0, 0.1 * n0], self.tol, self.max_iter);
+        let position = Point2::new(min.x[0], min.x[1]);
+        Some(Fit{ position
+                , n_photons : min.x[2]
+                , nll       : min.f
+                , deviance  : self.deviance(img, &position, min.x[2])
+                , ndof      : img.len().saturating_sub(3)
+                , iterations: min.iterations
+                , converged : min.converged
+                })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use nalgebra::point;
+    use crate::random::poisson;
+    use crate::reco::SolidAngle;
+
+    fn layout() -> SipmLayout {
+        SipmLayout::grid((10, 10), (6.0, 6.0), (0.5, 0.5), (0.0, 0.0))
+    }
+
+    fn sample(response: &SolidAngle, p: &Point2<f64>, n_photons: f64) -> DMatrix<usize> {
+        response.expected(p).map(|f| poisson(n_photons * f) as usize)
+    }
+
+    #[test]
+    fn recovers_truth() {
+        crate::random::seed(36);
+        let layout   = layout();
+        let response = SolidAngle::new(&layout, 5.0);
+        let truth    = point!(3.1, -7.4);
+        let img      = sample(&response, &truth, 20_000.0);
+        let fit      = LikelihoodFit::new(response).fit(&img, &layout).unwrap();
+        assert!(fit.converged);
+        assert!((fit.position - truth).norm() < 0.3, "{}", fit.position);
+        assert_float_eq!(fit.n_photons, 20_000.0, rmax<=0.05);
+        assert!(fit.deviance / (fit.ndof as f64) < 2.0, "{}", fit.deviance);
+    }
+
+    #[test]
+    fn better_than_barycenter_at_the_edge() {
+        crate::random::seed(37);
+        let layout   = layout();
+        let response = SolidAngle::new(&layout, 5.0);
+        let truth    = point!(30.0, 1.0);
+        let img      = sample(&response, &truth, 20_000.0);
+        let bary     = Barycenter::new(0, Some(3)).reconstruct(&img, &layout).unwrap();
+        let fit      = LikelihoodFit::new(response).fit(&img, &layout).unwrap();
+        assert!((fit.position - truth).norm() < (bary - truth).norm());
+    }
+
+    #[test]
+    fn deviance_of_perfect_data() {
+        let layout   = layout();
+        let response = SolidAngle::new(&layout, 5.0);
+        let p        = point!(0.0, 0.0);
+        let img      = response.expected(&p).map(|f| (1e6 * f).round() as usize);
+        let fitter   = LikelihoodFit::new(response);
+        assert!(fitter.deviance(&img, &p, 1e6) < 1.0);
+    }
+
+    #[test]
+    fn empty_image() {
+        let layout = layout();
+        let img    = DMatrix::zeros(10, 10);
+        assert!(LikelihoodFit::new(SolidAngle::new(&layout, 5.0)).fit(&img, &layout).is_none());
+    }
+}
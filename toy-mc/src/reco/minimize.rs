@@ -0,0 +1,106 @@
+/// Result of a minimization.
+#[derive(Debug, Clone)]
+pub struct Minimum {
+    pub x         : Vec<f64>,
+    pub f         : f64,
+    pub iterations: usize,
+    pub converged : bool,
+}
+
+/// Nelder-Mead simplex minimization of `f` starting at `x0`, with an initial
+/// simplex spanned by `step` along each axis. Converges when the spread of
+/// the function values in the simplex falls below `tol`.
+pub fn nelder_mead<F: Fn(&[f64]) -> f64>(f: F, x0: &[f64], step: &[f64], tol: f64, max_iter: usize) -> Minimum {
+    let (alpha, gamma, rho, sigma) = (1.0, 2.0, 0.5, 0.5);
+    let n = x0.len();
+
+    let mut simplex : Vec<(Vec<f64>, f64)> = Vec::with_capacity(n + 1);
+    simplex.push((x0.to_vec(), f(x0)));
+    for i in 0..n {
+        let mut x = x0.to_vec();
+        x[i] += step[i];
+        let fx = f(&x);
+        simplex.push((x, fx));
+    }
+
+    // x0 + t * (x1 - x0)
+    let along = |x0: &[f64], x1: &[f64], t: f64| -> Vec<f64> {
+        x0.iter().zip(x1).map(|(a, b)| a + t * (b - a)).collect()
+    };
+
+    let mut iterations = 0;
+    let mut converged  = false;
+    while iterations < max_iter {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0].1, simplex[n].1);
+        if (worst - best).abs() <= tol {
+            converged = true;
+            break
+        }
+        iterations += 1;
+
+        let centroid : Vec<f64> =
+            (0..n).map(|i| simplex[..n].iter().map(|(x, _)| x[i]).sum::<f64>() / n as f64)
+                  .collect();
+        let xr = along(&centroid, &simplex[n].0, -alpha);
+        let fr = f(&xr);
+
+        if fr < best {
+            let xe = along(&centroid, &simplex[n].0, -gamma);
+            let fe = f(&xe);
+            simplex[n] = if fe < fr { (xe, fe) } else { (xr, fr) };
+        } else if fr < simplex[n-1].1 {
+            simplex[n] = (xr, fr);
+        } else {
+            // outside contraction if the reflection improved on the worst
+            let target = if fr < worst { &xr } else { &simplex[n].0 };
+            let xc     = along(&centroid, target, rho);
+            let fc     = f(&xc);
+            if fc < worst.min(fr) {
+                simplex[n] = (xc, fc);
+            } else {
+                let x_best = simplex[0].0.clone();
+                for (x, fx) in simplex.iter_mut().skip(1) {
+                    *x  = along(&x_best, x, sigma);
+                    *fx = f(x);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let (x, f) = simplex.swap_remove(0);
+    Minimum{x, f, iterations, converged}
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn quadratic() {
+        let f   = |x: &[f64]| (x[0] - 1.0).powi(2) + 4.0 * (x[1] + 2.0).powi(2);
+        let min = nelder_mead(f, &[0.0, 0.0], &[0.5, 0.5], 1e-12, 1000);
+        assert!(min.converged);
+        assert_float_eq!(min.x[0],  1.0, abs<=1e-4);
+        assert_float_eq!(min.x[1], -2.0, abs<=1e-4);
+    }
+
+    #[test]
+    fn rosenbrock() {
+        let f   = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0]*x[0]).powi(2);
+        let min = nelder_mead(f, &[-1.2, 1.0], &[0.1, 0.1], 1e-14, 5000);
+        assert!(min.converged);
+        assert_float_eq!(min.x[0], 1.0, abs<=1e-3);
+        assert_float_eq!(min.x[1], 1.0, abs<=1e-3);
+    }
+
+    #[test]
+    fn iteration_limit() {
+        let f   = |x: &[f64]| x[0].powi(2);
+        let min = nelder_mead(f, &[10.0], &[1.0], 0.0, 5);
+        assert!(!min.converged);
+        assert_eq!(min.iterations, 5);
+    }
+}
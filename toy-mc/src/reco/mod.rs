@@ -0,0 +1,13 @@
+mod barycenter;
+mod response;
+mod minimize;
+mod likelihood;
+mod wires;
+mod energy;
+
+pub use barycenter::{Barycenter, window};
+pub use response::{Response, SolidAngle};
+pub use minimize::{nelder_mead, Minimum};
+pub use likelihood::{LikelihoodFit, Fit};
+pub use wires::{wire_centroid, WireCombination};
+pub use energy::{EnergyEstimator, calibrate};
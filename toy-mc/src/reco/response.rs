@@ -0,0 +1,107 @@
+use std::f64::consts::TAU;
+use nalgebra::{DMatrix, Point2};
+
+use crate::{SipmLayout, Sensor};
+
+/// Expected light response of the SiPM plane: the fraction of the photons
+/// emitted towards the plane from `p` that reach each sensor, with the shape
+/// of the event image.
+pub trait Response {
+    fn expected(&self, p: &Point2<f64>) -> DMatrix<f64>;
+}
+
+impl<R: Response + ?Sized> Response for Box<R> {
+    fn expected(&self, p: &Point2<f64>) -> DMatrix<f64> {
+        (**self).expected(p)
+    }
+}
+
+/// Solid angle of the rectangle [0, x] x [0, y] seen from a point at
+/// `height` above its corner.
+fn corner_solid_angle(x: f64, y: f64, height: f64) -> f64 {
+    (x * y / (height * (x*x + y*y + height*height).sqrt())).atan()
+}
+
+/// Solid angle of a sensor seen from a point at `height` above `p`.
+fn solid_angle(sensor: &Sensor, p: &Point2<f64>, height: f64) -> f64 {
+    let x1 = sensor.x - sensor.size_x / 2.0 - p.x;
+    let x2 = sensor.x + sensor.size_x / 2.0 - p.x;
+    let y1 = sensor.y - sensor.size_y / 2.0 - p.y;
+    let y2 = sensor.y + sensor.size_y / 2.0 - p.y;
+    corner_solid_angle(x2, y2, height) - corner_solid_angle(x1, y2, height)
+  - corner_solid_angle(x2, y1, height) + corner_solid_angle(x1, y1, height)
+}
+
+/// Point-like isotropic emission at `height` from the SiPM plane, without
+/// shadowing. Wires and meshes reduce the light roughly uniformly, which the
+/// fitted number of photons absorbs.
+#[derive(Debug, Clone)]
+pub struct SolidAngle {
+    sensors: Vec<Sensor>,
+    shape  : (usize, usize),
+    height : f64,
+}
+
+impl SolidAngle {
+    pub fn new(layout: &SipmLayout, height: f64) -> Self {
+        Self{ sensors: layout.sensors(), shape: layout.shape(), height }
+    }
+}
+
+impl Response for SolidAngle {
+    fn expected(&self, p: &Point2<f64>) -> DMatrix<f64> {
+        let (rows, cols) = self.shape;
+        DMatrix::from_fn(rows, cols, |r, c| solid_angle(&self.sensors[r * cols + c], p, self.height) / TAU)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use float_eq::assert_float_eq;
+    use nalgebra::point;
+    use crate::simulation::project;
+    use crate::random::uniform;
+
+    #[test]
+    fn infinite_plane_is_half_the_sphere() {
+        let sensor = Sensor{x: 0.0, y: 0.0, size_x: 1e9, size_y: 1e9};
+        assert_float_eq!(solid_angle(&sensor, &point!(3.0, -2.0), 1.0), TAU, rmax<=1e-6);
+    }
+
+    #[test]
+    fn square_seen_from_centre() {
+        // a square of side 2h seen from a height h subtends 4π/6
+        let sensor = Sensor{x: 1.0, y: 1.0, size_x: 2.0, size_y: 2.0};
+        assert_float_eq!(solid_angle(&sensor, &point!(1.0, 1.0), 1.0), 4.0 * PI / 6.0, rmax<=1e-12);
+    }
+
+    #[test]
+    fn symmetric() {
+        let layout   = SipmLayout::grid((4, 4), (1.0, 1.0), (0.5, 0.5), (0.0, 0.0));
+        let response = SolidAngle::new(&layout, 2.0).expected(&point!(0.0, 0.0));
+        assert_float_eq!(response[(0, 0)], response[(3, 3)], rmax<=1e-12);
+        assert_float_eq!(response[(1, 2)], response[(2, 1)], rmax<=1e-12);
+        assert!(response[(1, 1)] > response[(0, 0)]);
+    }
+
+    #[test]
+    fn matches_sampling() {
+        crate::random::seed(35);
+        let layout   = SipmLayout::grid((3, 3), (2.0, 2.0), (1.0, 1.0), (0.0, 0.0));
+        let p0       = point!(0.7, -0.4);
+        let height   = 3.0;
+        let response = SolidAngle::new(&layout, height).expected(&p0);
+        let n        = 200_000;
+        let mut hits = DMatrix::<f64>::zeros(3, 3);
+        for _ in 0..n {
+            let pos = project(&point!(p0.x, p0.y, 0.0), uniform(0.0, 1.0), uniform(0.0, TAU), height);
+            if let Some(idx) = layout.locate(&pos) { hits[idx] += 1.0 / n as f64; }
+        }
+        for (got, expected) in hits.iter().zip(response.iter()) {
+            assert_float_eq!(got, expected, abs<=3e-3);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use nalgebra::{point, Point2, Rotation2};
+use derive_new::new;
+
+use crate::WirePlane;
+
+/// Charge-weighted mean of the wire positions, along the axis perpendicular
+/// to the wires in the wire frame. `None` if there is no charge.
+pub fn wire_centroid(q: &[usize], wire_pos: &[f64]) -> Option<f64> {
+    let norm : usize = q.iter().sum();
+    if norm == 0 { return None }
+    let sum : f64 = q.iter().zip(wire_pos).map(|(&q, &x)| q as f64 * x).sum();
+    Some(sum / norm as f64)
+}
+
+/// Combination of a SiPM estimate with the wire centroid. The SiPM estimate
+/// is rotated to the wire frame, averaged with the wire centroid along the
+/// axis perpendicular to the wires, weighted by their resolutions, and
+/// rotated back. `rotation` goes from the wire frame to the SiPM frame.
+#[derive(new, Debug, Clone, Copy)]
+pub struct WireCombination {
+    pub rotation  : Rotation2<f64>,
+    pub wire_sigma: f64,
+    pub sipm_sigma: f64,
+}
+
+impl WireCombination {
+    /// Wire resolution of a single wire collecting all the charge, pitch/√12.
+    pub fn for_wires(wires: &WirePlane, sipm_sigma: f64) -> Self {
+        Self::new(wires.rotation(), wires.wire_pitch / 12f64.sqrt(), sipm_sigma)
+    }
+
+    /// Joint estimate in the SiPM frame.
+    pub fn combine(&self, sipm: &Point2<f64>, x_wire: f64) -> Point2<f64> {
+        let p  = self.rotation.inverse() * sipm;
+        let ws = self.sipm_sigma.powi(-2);
+        let ww = self.wire_sigma.powi(-2);
+        let x  = (ws * p.x + ww * x_wire) / (ws + ww);
+        self.rotation * point!(x, p.y)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn centroid() {
+        let pos = [-7.5, -2.5, 2.5, 7.5];
+        assert_eq!(wire_centroid(&[0, 0, 0, 0], &pos), None);
+        assert_float_eq!(wire_centroid(&[0, 10, 0, 0], &pos).unwrap(), -2.5, abs<=1e-12);
+        assert_float_eq!(wire_centroid(&[0, 30, 10, 0], &pos).unwrap(), -1.25, abs<=1e-12);
+    }
+
+    #[test]
+    fn weights() {
+        let comb = WireCombination::new(Rotation2::identity(), 1.0, 1.0);
+        let p    = comb.combine(&point!(2.0, 3.0), 4.0);
+        assert_float_eq!(p.x, 3.0, abs<=1e-12);
+        assert_float_eq!(p.y, 3.0, abs<=1e-12);
+
+        let comb = WireCombination::new(Rotation2::identity(), 1e-6, 1.0);
+        assert_float_eq!(comb.combine(&point!(2.0, 3.0), 4.0).x, 4.0, abs<=1e-9);
+    }
+
+    #[test]
+    fn rotated() {
+        // wires along x in the SiPM frame: the wire coordinate is -y
+        let comb = WireCombination::new(Rotation2::new(-FRAC_PI_2), 1e-6, 1.0);
+        let p    = comb.combine(&point!(2.0, 3.0), 5.0);
+        assert_float_eq!(p.x,  2.0, abs<=1e-6);
+        assert_float_eq!(p.y, -5.0, abs<=1e-6);
+    }
+}
@@ -1,13 +1,30 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use derive_new::new;
 
-#[derive(new, Debug, Deserialize, Serialize, Clone)]
+use crate::units;
+
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct SimParams {
+    /// Energy deposited per event, eV
+    #[serde(deserialize_with = "units::energy")]
+    #[schemars(with = "units::Quantity")]
     pub dep_energy : f64,
+    /// Mean energy to produce an ionization electron, eV
+    #[serde(deserialize_with = "units::energy")]
+    #[schemars(with = "units::Quantity")]
     pub w_i        : f64,
+    /// EL photons emitted per electron
     pub light_yield: f64,
+    /// Range of distances to the wire along which light is emitted, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub el_range   : f64,
+    /// Radius of the electron cloud, mm. Point-like by default
+    #[serde(default, deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub cloud_r    : f64,
+    /// Fano factor of the number of electrons
     pub fano_factor: f64,
 }
 
@@ -15,9 +32,21 @@ impl SimParams {
     pub fn n_ie_ave(&self) -> f64 {
         self.dep_energy / self.w_i
     }
+
+    /// Mean number of photons emitted towards the SiPM plane per event.
+    pub fn n_photons_ave(&self) -> f64 {
+        self.n_ie_ave() * self.light_yield / 2.0
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+
+    #[test]
+    fn optional_parameters() {
+        let params : SimParams = toml::from_str("dep_energy = \"1 keV\"\nw_i = 20\nlight_yield = 10\nel_range = 0.1\nfano_factor = 0.1").unwrap();
+        assert_eq!(params.cloud_r   , 0.0);
+        assert_eq!(params.dep_energy, 1000.0);
+    }
 }
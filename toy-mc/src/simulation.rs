@@ -1,22 +1,33 @@
 use std::f64::consts::{PI, TAU};
 use nalgebra::{point, Point2, Point3, vector};
 
-use crate::random::{uniform, poisson, normal, random_in_circle};
+use crate::{Mesh, Photon};
+use crate::random::{uniform, poisson, normal, exponential, random_in_circle};
+
+/// Speed of light in mm/ns. The refractive index of the gas is neglected.
+const C_LIGHT: f64 = 299.792458;
 
 pub fn generate_el_position(el_r: f64) -> Point2<f64> {
     random_in_circle(el_r)
 }
 
+/// Electrons spread uniformly over a disc of radius `cloud_r` around `p0`.
+/// Their number has variance `fano_factor * n_ave`, or is Poisson for small
+/// `n_ave`.
 pub fn generate_electrons(p0: Point2<f64>, n_ave: f64, fano_factor: f64, cloud_r: f64) -> Vec<Point2<f64>> {
     let n = if n_ave < 10.0 { poisson(n_ave) as usize }
-    else { normal(n_ave, n_ave.sqrt() * fano_factor).round() as usize };
+    else { normal(n_ave, (n_ave * fano_factor).sqrt()).round() as usize };
 
     let p0 = p0 - Point2::origin();
-    (0..n).into_iter()
+    (0..n)
           .map(|_| random_in_circle(cloud_r) + p0)
           .collect()
 }
 
+pub fn electron_arrival_time(drift_time: f64, long_diffusion: f64) -> f64 {
+    normal(drift_time, long_diffusion)
+}
+
 pub fn propagate_to_wire(p0: Point2<f64>, wire_pitch: f64, first_wire: f64, wire_r: f64, el_range: f64) -> (Point3<f64>, usize) {
     let dx       = p0.x - (first_wire - wire_pitch/2.);
     let n_wire   = (dx / wire_pitch).floor();
@@ -29,34 +40,88 @@ pub fn propagate_to_wire(p0: Point2<f64>, wire_pitch: f64, first_wire: f64, wire
     (point!(x + wire_pos, p0.y, z), n_wire as usize)
 }
 
-fn is_shadowed(p0: &Point3<f64>, pwire: &Point3<f64>, wire_r: f64, cos_th: f64, phi: f64) -> bool {
+/// Whether the ray from `p0` along (`cos_th`, `phi`) hits the wire of radius
+/// `wire_r` through `pwire`, parallel to the y axis.
+pub fn is_shadowed(p0: &Point3<f64>, pwire: &Point3<f64>, wire_r: f64, cos_th: f64, phi: f64) -> bool {
     let sin_th = (1.0 - cos_th.powi(2)).sqrt();
     let axis   = vector!(    pwire.x - p0.x, pwire.z - p0.z);
     let ray    = vector!(sin_th * phi.cos(),         cos_th);
 
     let a =  ray.dot(&ray );
-    let b =  ray.dot(&axis); // negative sign irrelevant, factor 2 factored out
+    let b =  ray.dot(&axis); // factor 2 factored out, roots at t = (b ± sqrt(b² - ac))/a
     let c = axis.dot(&axis) - wire_r*wire_r;
-    b*b >= a*c
+    b*b >= a*c && b > 0.0 // only forward intersections
 }
 
-pub fn propagate_light(p0: Point3<f64>, pwire: Point3<f64>, light_yield: f64, distance: f64, wire_r: f64) -> Vec<Point2<f64>> {
+/// Checks the ray against every wire it may cross. Only the wires whose
+/// position falls within the x-range spanned by the ray while traversing the
+/// wire plane (|z| <= wire_r) are tested.
+pub fn is_shadowed_by_wires(p0: &Point3<f64>, wires: &[f64], wire_r: f64, cos_th: f64, phi: f64) -> bool {
+    if cos_th <= 0.0 { return true } // parallel to the plane, never reaches the SiPMs
+
+    let sin_th = (1.0 - cos_th.powi(2)).sqrt();
+    let t_lo   = ((-wire_r - p0.z) / cos_th).max(0.0);
+    let t_hi   =  ( wire_r - p0.z) / cos_th;
+    if t_hi < 0.0 { return false }
+
+    let dx    = sin_th * phi.cos();
+    let xa    = p0.x + t_lo * dx;
+    let xb    = p0.x + t_hi * dx;
+    let lo    = xa.min(xb) - wire_r;
+    let hi    = xa.max(xb) + wire_r;
+    let first = wires.partition_point(|&w| w <  lo);
+    let last  = wires.partition_point(|&w| w <= hi);
+    wires[first..last]
+        .iter()
+        .any(|&w| is_shadowed(p0, &point!(w, p0.y, 0.0), wire_r, cos_th, phi))
+}
+
+/// Position of the ray at height `z`.
+pub fn project(p0: &Point3<f64>, cos_th: f64, phi: f64, z: f64) -> Point2<f64> {
+    let theta = cos_th.acos();
+    let r     = (z - p0.z) * theta.tan();
+    point!(p0.x + r * phi.cos(), p0.y + r * phi.sin())
+}
+
+fn is_blocked_by_mesh(p0: &Point3<f64>, mesh: &Mesh, cos_th: f64, phi: f64) -> bool {
+    let p = project(p0, cos_th, phi, mesh.z);
+    mesh.hits_grid(p.x, p.y) || uniform(0.0, 1.0) >= mesh.transparency
+}
+
+/// Light emitted by an electron reaching `p0` at time `t0`: the number of
+/// photons emitted towards the SiPM plane and those traced. The photons
+/// blocked by wires or meshes are only traced if `keep_shadowed`, flagged
+/// as `shadowed` and without an arrival time. Each photon reaching the plane
+/// is delayed by the EL emission time (exponential with mean `el_tau`) and
+/// its time of flight.
+#[allow(clippy::too_many_arguments)]
+pub fn trace_light(p0: Point3<f64>, t0: f64, wires: &[f64], wire_r: f64, meshes: &[Mesh], light_yield: f64, distance: f64, el_tau: f64, keep_shadowed: bool) -> (usize, Vec<Photon>) {
     let light_yield = light_yield / 2.0;
      // TODO: consider CP factor
     // let n = normal(light_yield, light_yield.sqrt() * cp_factor).round() as usize;
     let n = poisson(light_yield) as usize;
 
-    (0..n).into_iter()
-        .map   (|_| (uniform(0.0, 1.0), uniform(0.0, TAU)))
-        .filter(|(cos_th, phi)| !is_shadowed(&p0, &pwire, wire_r, *cos_th, *phi))
-        .map   (|(cos_th, phi)| {
-            let theta = cos_th.acos();
-            let z     = distance - p0.z; // p0.z is negative
-            let x     = z * theta.tan() * phi.cos();
-            let y     = x               * phi.tan();
-            point!(x + p0.x, y + p0.y)
+    let photons = (0..n)
+        .map(|_| (uniform(0.0, 1.0), uniform(0.0, TAU)))
+        .filter_map(|(cos_th, phi)| {
+            let shadowed = is_shadowed_by_wires(&p0, wires, wire_r, cos_th, phi)
+                        || meshes.iter()
+                                 .filter(|m| p0.z < m.z && m.z < distance)
+                                 .any   (|m| is_blocked_by_mesh(&p0, m, cos_th, phi));
+            if shadowed && !keep_shadowed { return None }
+            let pos  = project(&p0, cos_th, phi, distance);
+            let time = if shadowed { f64::NAN }
+                       else        { t0 + exponential(el_tau) + (distance - p0.z) / cos_th / C_LIGHT };
+            Some(Photon{pos, time, origin: p0, shadowed})
         })
-        .collect()
+        .collect();
+    (n, photons)
+}
+
+/// Photons reaching the SiPM plane, see `trace_light`.
+#[allow(clippy::too_many_arguments)]
+pub fn propagate_light(p0: Point3<f64>, t0: f64, wires: &[f64], wire_r: f64, meshes: &[Mesh], light_yield: f64, distance: f64, el_tau: f64) -> Vec<Photon> {
+    trace_light(p0, t0, wires, wire_r, meshes, light_yield, distance, el_tau, false).1
 }
 
 
@@ -64,8 +129,22 @@ pub fn propagate_light(p0: Point3<f64>, pwire: Point3<f64>, light_yield: f64, di
 mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
-    use std::f64::consts::{FRAC_PI_2, PI};
-    use crate::random::uniform;
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, PI};
+    use float_eq::assert_float_eq;
+    use crate::random::{uniform, seed};
+    use crate::analysis::{mean, bin, ks_distance, ks_p_value, chi2, chi2_p_value};
+
+    fn variance(values: &[f64]) -> f64 {
+        let m = mean(values);
+        values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+    }
+
+    /// Counts of `values` in `n` equal bins spanning [`lo`, `hi`).
+    fn histogram(values: &[f64], lo: f64, hi: f64, n: usize) -> Vec<f64> {
+        let mut counts = vec![0.0; n];
+        values.iter().filter_map(|&v| bin(v, lo, hi, n)).for_each(|i| counts[i] += 1.0);
+        counts
+    }
 
     #[test]
     fn generation_within_el() {
@@ -152,4 +231,192 @@ mod tests {
         }
     }
 
+    #[test]
+    fn shadow_backwards_ignored() {
+        let p0     = point!(0.0, 0.0,  1.0);
+        let p1     = point!(0.0, 0.0,  0.0);
+        let outcome = is_shadowed(&p0, &p1, 0.5, 1.0, 0.0);
+        assert!(!outcome);
+    }
+
+    #[test]
+    fn shadow_by_neighbour() {
+        // Shallow ray emitted next to the central wire towards the right
+        let p0     = point!(0.6, 0.0, -0.1);
+        let r      = 0.5;
+        let cos_th = 0.3;
+        assert!( is_shadowed_by_wires(&p0, &[-2.0, 0.0, 2.0], r, cos_th, 0.0));
+        assert!(!is_shadowed_by_wires(&p0, &[-2.0, 0.0     ], r, cos_th, 0.0));
+        assert!(!is_shadowed_by_wires(&p0, &[       0.0     ], r, cos_th, 0.0));
+    }
+
+    #[test]
+    fn shadow_vertical_between_wires() {
+        let wires = [-2.0, 0.0, 2.0, 4.0];
+        let r     = 0.5;
+        for _ in 0..10_000 {
+            let x        = uniform(-2.0, 4.0);
+            let p0       = point!(x, 0.0, -1.0);
+            let outcome  = is_shadowed_by_wires(&p0, &wires, r, 1.0, 0.0);
+            let expected = wires.iter().any(|w| (x - w).abs() < r);
+            assert_eq!(outcome, expected, "failed for x {}", x);
+        }
+    }
+
+    #[test]
+    fn projection() {
+        let p0 = point!(1.0, 2.0, -1.0);
+        let p  = project(&p0, FRAC_1_SQRT_2, 0.0, 1.0);
+        assert_float_eq!(p.x, 3.0, abs<=1e-12);
+        assert_float_eq!(p.y, 2.0, abs<=1e-12);
+
+        let p  = project(&p0, FRAC_1_SQRT_2, FRAC_PI_2, 1.0);
+        assert_float_eq!(p.x, 1.0, abs<=1e-12);
+        assert_float_eq!(p.y, 4.0, abs<=1e-12);
+    }
+
+    #[test]
+    fn opaque_mesh_blocks_everything() {
+        let p0      = point!(0.0, 0.0, -1.0);
+        let opaque  = Mesh::new(1.0, 1.0, 0.0, 0.0);
+        let full    = Mesh::new(1.0, 1.0, 1.0, 1.0);
+        let beyond  = Mesh::new(9.0, 1.0, 1.0, 0.0);
+        let behind  = Mesh::new(-2.0, 1.0, 1.0, 0.0);
+        assert!(propagate_light(p0, 0.0, &[], 0.5, &[opaque], 1e4, 5.0, 0.0).is_empty());
+        assert!(propagate_light(p0, 0.0, &[], 0.5, &[full  ], 1e4, 5.0, 0.0).is_empty());
+        assert!(!propagate_light(p0, 0.0, &[], 0.5, &[beyond], 1e4, 5.0, 0.0).is_empty());
+        assert!(!propagate_light(p0, 0.0, &[], 0.5, &[behind], 1e4, 5.0, 0.0).is_empty());
+    }
+
+    #[test]
+    fn photon_times() {
+        let p0       = point!(0.0, 0.0, -1.0);
+        let t0       = 123.4;
+        let distance = 5.0;
+        let photons  = propagate_light(p0, t0, &[], 0.5, &[], 1e4, distance, 0.0);
+        assert!(!photons.is_empty());
+        for ph in photons {
+            let path = ((ph.pos - p0.xy()).norm_squared() + (distance - p0.z).powi(2)).sqrt();
+            assert_float_eq!(ph.time, t0 + path / C_LIGHT, rmax<=1e-9);
+        }
+    }
+
+    #[test]
+    fn photon_times_el_delay() {
+        let p0      = point!(0.0, 0.0, -1.0);
+        let photons = propagate_light(p0, 0.0, &[], 0.5, &[], 1e4, 5.0, 100.0);
+        let mean    = photons.iter().map(|ph| ph.time).sum::<f64>() / photons.len() as f64;
+        assert!(photons.iter().all(|ph| ph.time > 0.0));
+        assert!((mean - 100.0).abs() < 10.0, "mean {}", mean);
+    }
+
+    #[test]
+    fn traced_photons_flagged() {
+        let p0       = point!(0.0, 0.0, -1.0);
+        let wires    = [0.0];
+        let (n, photons) = trace_light(p0, 0.0, &wires, 0.5, &[], 1e4, 5.0, 0.0, true);
+        let shadowed = photons.iter().filter(|ph| ph.shadowed).count();
+        assert_eq!(photons.len(), n);
+        assert!(shadowed > 0);
+        assert!(shadowed < photons.len());
+        assert!(photons.iter().all(|ph| ph.shadowed == ph.time.is_nan()));
+        // In the xz plane the ray passes at |x|/sqrt(x² + 36) from the wire,
+        // x being the landing position
+        for ph in photons {
+            let expected = ph.pos.x.abs() < 12_f64.sqrt();
+            assert_eq!(ph.shadowed, expected, "failed for x {}", ph.pos.x);
+            assert_eq!(ph.origin, p0);
+        }
+    }
+
+    #[test]
+    fn mesh_blocks_grid_lines() {
+        // Vertical photons through a mesh: blocked iff they start on a line
+        let mesh = Mesh::new(1.0, 1.0, 0.2, 1.0);
+        for _ in 0..10_000 {
+            let x        = uniform(-3.0, 3.0);
+            let y        = uniform(-3.0, 3.0);
+            let p0       = point!(x, y, -1.0);
+            let outcome  = is_blocked_by_mesh(&p0, &mesh, 1.0, 0.0);
+            let near     = |u: f64| (u - u.round()).abs() < 0.1;
+            assert_eq!(outcome, near(x) || near(y), "failed for x {} y {}", x, y);
+        }
+    }
+
+    #[test]
+    fn electron_count_fano() {
+        seed(4801);
+        let (n_ave, fano) = (200.0, 0.15);
+        let counts : Vec<f64> = (0..10_000).map(|_| generate_electrons(Point2::origin(), n_ave, fano, 0.0).len() as f64).collect();
+        let n      = counts.len() as f64;
+        // rounding the gaussian adds 1/12 to the variance
+        let var    = n_ave * fano + 1.0 / 12.0;
+        assert!((mean(&counts) - n_ave).abs() < 5.0 * (var / n).sqrt(), "mean {}", mean(&counts));
+        assert!((variance(&counts) - var).abs() < 5.0 * var * (2.0 / n).sqrt(), "variance {}", variance(&counts));
+    }
+
+    #[test]
+    fn electron_count_poisson() {
+        seed(4802);
+        let n_ave  = 4.0;
+        let counts : Vec<f64> = (0..10_000).map(|_| generate_electrons(Point2::origin(), n_ave, 0.15, 0.0).len() as f64).collect();
+        let n      = counts.len() as f64;
+        assert!((mean    (&counts) - n_ave).abs() < 5.0 * (n_ave / n).sqrt());
+        assert!((variance(&counts) - n_ave).abs() < 5.0 * n_ave * ((2.0 + 1.0 / n_ave) / n).sqrt());
+    }
+
+    #[test]
+    fn photon_count_unshadowed() {
+        seed(4803);
+        // A wire of radius 0.5 at distance 1 hides the rays with
+        // |tan θ cos φ| < 1/sqrt(3), i.e. cos θ > c / sqrt(c² + 1/3), c = |cos φ|
+        let p0          = point!(0.0, 0.0, -1.0);
+        let k2          = 1.0 / 3.0;
+        let n_phi       = 100_000;
+        let unshadowed  = (0..n_phi).map(|i| (TAU * (i as f64 + 0.5) / n_phi as f64).cos().abs())
+                                    .map(|c| c / (c * c + k2).sqrt())
+                                    .sum::<f64>() / n_phi as f64;
+        let light_yield = 20.0;
+        let expected    = light_yield / 2.0 * unshadowed;
+        let counts : Vec<f64> = (0..10_000).map(|_| propagate_light(p0, 0.0, &[0.0], 0.5, &[], light_yield, 5.0, 0.0).len() as f64).collect();
+        let n           = counts.len() as f64;
+        assert!((mean(&counts) - expected).abs() < 5.0 * (expected / n).sqrt(), "mean {} expected {expected}", mean(&counts));
+
+        // Poisson distributed, the last bin holding the tail
+        let n_bins   = 20;
+        let mut pmf  = vec![(-expected).exp()];
+        for k in 1..n_bins { pmf.push(pmf[k - 1] * expected / k as f64) }
+        pmf[n_bins - 1] += 1.0 - pmf.iter().sum::<f64>();
+        let clipped  : Vec<f64> = counts.iter().map(|c| c.min((n_bins - 1) as f64)).collect();
+        let observed = histogram(&clipped, 0.0, n_bins as f64, n_bins);
+        let expected = pmf.iter().map(|p| p * n).collect::<Vec<_>>();
+        let p_value  = chi2_p_value(chi2(&observed, &expected), n_bins - 1);
+        assert!(p_value > 1e-3, "p-value {p_value}");
+    }
+
+    #[test]
+    fn hits_isotropic() {
+        seed(4804);
+        // Emitted uniformly over the forward hemisphere: cos θ and φ uniform
+        let p0       = point!(1.0, 2.0, -1.0);
+        let distance = 5.0;
+        let height   = distance - p0.z;
+        let photons  = propagate_light(p0, 0.0, &[], 0.5, &[], 2e4, distance, 0.0);
+        let cos_th   : Vec<f64> = photons.iter().map(|ph| height / (ph.pos - p0.xy()).norm().hypot(height)).collect();
+        let phi      : Vec<f64> = photons.iter().map(|ph| { let d = ph.pos - p0.xy(); d.y.atan2(d.x).rem_euclid(TAU) }).collect();
+
+        let d = ks_distance(&cos_th, |c| c.clamp(0.0, 1.0));
+        assert!(ks_p_value(d, cos_th.len()) > 1e-3, "cos θ KS distance {d}");
+
+        let counts   = histogram(&phi, 0.0, TAU, 24);
+        let expected = vec![phi.len() as f64 / 24.0; 24];
+        let p_value  = chi2_p_value(chi2(&counts, &expected), 23);
+        assert!(p_value > 1e-3, "φ p-value {p_value}");
+
+        // hits within r of the foot of p0 cover the solid angle 2π (1 - cos θ(r))
+        let r        = 4.0;
+        let inside   = photons.iter().filter(|ph| (ph.pos - p0.xy()).norm() < r).count() as f64 / photons.len() as f64;
+        let solid    = 1.0 - height / r.hypot(height);
+        assert!((inside - solid).abs() < 5.0 * (solid * (1.0 - solid) / photons.len() as f64).sqrt(), "{inside} vs {solid}");
+    }
 }
@@ -0,0 +1,245 @@
+use std::io;
+use nalgebra::{point, Point2, Rotation2};
+
+use crate::{SimConfig, SipmLayout, Image, Event, Photon, PhotonRecord, Calibrator, WireReadout};
+use crate::simulation::{generate_el_position, generate_electrons, electron_arrival_time, propagate_to_wire, trace_light};
+
+/// Full event simulation for a configuration: electrons, wires, light, the
+/// SiPM images and the optional waveforms, calibration and wire readout.
+/// Built once per run, the images are reused between events.
+pub struct Simulator {
+    conf      : SimConfig,
+    all_wires : Vec<f64>,
+    first_wire: f64,
+    rotation  : Rotation2<f64>,
+    layout    : SipmLayout,
+    img       : Image,
+    img_fine  : Option<Image>,
+    calib     : Option<Calibrator>,
+    readout   : Option<WireReadout>,
+}
+
+impl Simulator {
+    pub fn new(conf: &SimConfig) -> io::Result<Self> {
+        let wires      = &conf.geometry.wire_plane;
+        let all_wires  = wires.wire_pos();
+        let first_wire = *all_wires.first().unwrap();
+        let img        = Image::for_sipms(&conf.geometry.sipm_plane)?;
+        let layout     = img.layout().clone();
+        let calib      = conf.calibration.as_ref()
+                             .map(|c| c.load(layout.n_channels(), wires.n_wires))
+                             .transpose()?;
+        Ok(Self{ conf      : conf.clone()
+               , all_wires
+               , first_wire
+               , rotation  : wires.rotation()
+               , layout
+               , img
+               , img_fine  : conf.detailed.as_ref().map(Image::for_detailed)
+               , calib
+               , readout   : conf.wire_readout()
+               })
+    }
+
+    pub fn conf(&self) -> &SimConfig {
+        &self.conf
+    }
+
+    pub fn layout(&self) -> &SipmLayout {
+        &self.layout
+    }
+
+    /// Rotation from the wire frame, where events are generated, to the
+    /// SiPM frame.
+    pub fn rotation(&self) -> Rotation2<f64> {
+        self.rotation
+    }
+
+    /// Event at a random position within the EL region.
+    pub fn simulate(&mut self, number: usize, photons: Option<&mut Vec<PhotonRecord>>) -> Event {
+        let position = generate_el_position(self.conf.geometry.el_gap.el_r);
+        self.simulate_at(number, position, photons)
+    }
+
+    /// Event at `position`, in the wire frame. The photons, in the SiPM
+    /// frame, are appended to `photons` if given. Electrons beyond the
+    /// outermost wires, at `|x| >= n_wires * wire_pitch / 2`, are dropped:
+    /// they reach no wire, emit no light and are not counted in
+    /// `n_electrons`.
+    pub fn simulate_at(&mut self, number: usize, position: Point2<f64>, mut photons: Option<&mut Vec<PhotonRecord>>) -> Event {
+        let wires      = &self.conf.geometry.wire_plane;
+        let params     = &self.conf.sim_params;
+        let timing     = self.conf.timing.as_ref();
+        let drift_time = timing.map_or(0.0, |t| t.drift_time);
+        let long_diff  = timing.map_or(0.0, |t| t.long_diffusion);
+        let el_tau     = timing.map_or(0.0, |t| t.el_tau);
+        let rotation   = self.rotation;
+
+        self.img.reset();
+        if let Some(img_fine) = self.img_fine.as_mut() { img_fine.reset(); }
+        let mut wire_q = vec![0usize; wires.n_wires];
+        let mut sipm_t = Vec::new();
+        let half_width = wires.n_wires as f64 * wires.wire_pitch / 2.0;
+        let ps         = generate_electrons(position, params.n_ie_ave(), params.fano_factor, params.cloud_r)
+                             .into_iter()
+                             .filter(|p| p.x.abs() < half_width)
+                             .collect::<Vec<_>>();
+        let n_electrons = ps.len();
+        let mut n_photons = 0;
+        for p0 in ps {
+            let (p1, iwire) = propagate_to_wire(p0, wires.wire_pitch, self.first_wire, wires.wire_r, params.el_range);
+            wire_q[iwire] += 1;
+            let t0   = electron_arrival_time(drift_time, long_diff);
+            let (n, hits) = trace_light(p1, t0, &self.all_wires, wires.wire_r, &self.conf.geometry.meshes, params.light_yield, self.conf.geometry.buffer, el_tau, photons.is_some());
+            n_photons += n;
+            for hit in &hits {
+                let pos = rotation * hit.pos;
+                if let Some(photons) = photons.as_mut() {
+                    let xy0    = rotation * hit.origin.xy();
+                    let origin = point!(xy0.x, xy0.y, hit.origin.z);
+                    let photon = Photon{pos, origin, ..*hit};
+                    let sipm   = if hit.shadowed { None } else { self.layout.channel(&pos) };
+                    photons.push(PhotonRecord{photon, wire: iwire, sipm});
+                }
+                if hit.shadowed { continue }
+
+                if let Some(img_fine) = self.img_fine.as_mut() { img_fine.fill(&pos); }
+                let channel = self.layout.channel(&pos);
+                // detected once, for both the image and the waveforms
+                let lost    = channel.zip(self.calib.as_ref()).is_some_and(|(ch, c)| !c.detect_photon(ch));
+                if lost { continue }
+
+                self.img.fill(&pos);
+                if timing.is_some() {
+                    if let Some(ch) = channel { sipm_t.push((ch, hit.time)); }
+                }
+            }
+        }
+        let waveforms = timing.map(|t| {
+            let wfs = t.sample(&sipm_t, self.layout.n_channels());
+            match &self.calib {
+                Some(c) => c.read_out_waveforms(wfs),
+                None    => wfs,
+            }
+        });
+        let fine_img  = self.img_fine.as_ref().map(Image::finalize);
+        let img       = self.img.finalize();
+        let n_detected = img.sum();
+        let img       = match &self.calib {
+            Some(c) => c.read_out_sipms(&img),
+            None    => img,
+        };
+        let wire_adc  = self.readout.as_ref().map(|r| r.digitize(&wire_q, self.calib.as_ref().map(|c| &c.wires)));
+        Event{number, position, n_electrons, n_photons, n_detected, wire_q, wire_adc, img, fine_img, waveforms}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use crate::{Calibration, Timing};
+
+    #[test]
+    fn event_contents() {
+        let conf    = SimConfig::new("conf/test.toml").unwrap();
+        let mut sim = Simulator::new(&conf).unwrap();
+        let mut photons = Vec::new();
+        let event   = sim.simulate_at(3, point!(1.0, 2.0), Some(&mut photons));
+        assert_eq!(event.number, 3);
+        assert_eq!(event.position, point!(1.0, 2.0));
+        assert_eq!(event.wire_q.len(), conf.geometry.wire_plane.n_wires);
+        assert_eq!(event.img.shape(), sim.layout().shape());
+        assert!(event.wire_adc.is_none());
+        assert!(event.waveforms.is_none());
+
+        let detected = photons.iter().filter(|p| p.sipm.is_some()).count();
+        assert_eq!(event.img.sum(), detected);
+        assert_eq!(event.n_detected, detected);
+        assert_eq!(event.n_photons , photons.len());
+        assert_eq!(event.n_electrons, event.wire_q.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn outside_the_wires() {
+        let conf    = SimConfig::new("conf/test.toml").unwrap();
+        let mut sim = Simulator::new(&conf).unwrap();
+        let event   = sim.simulate_at(0, point!(100.0, 0.0), None);
+        assert_eq!(event.wire_q.iter().sum::<usize>(), 0);
+        assert_eq!(event.n_electrons, 0);
+        assert_eq!(event.img.sum(), 0);
+    }
+
+    #[test]
+    fn dead_channel_waveform() {
+        crate::random::seed(32);
+        let conf      = SimConfig::new("preset:timing").unwrap();
+        let position  = point!(1.0, 2.0);
+        let wfs       = Simulator::new(&conf).unwrap().simulate_at(0, position, None).waveforms.unwrap();
+        let brightest = (0..wfs.nrows()).max_by(|&a, &b| wfs.row(a).sum().total_cmp(&wfs.row(b).sum())).unwrap();
+        assert!(wfs.row(brightest).sum() > 0.0);
+
+        let dir  = tempfile::tempdir().unwrap();
+        let map  = dir.path().join("sipm_map.csv");
+        std::fs::write(&map, format!("id, alive, gain\n{brightest}, false, 1.0\n")).unwrap();
+        let conf = SimConfig{calibration: Some(Calibration::new(Some(map.to_str().unwrap().to_owned()), None, 0.0)), ..conf};
+        let wfs  = Simulator::new(&conf).unwrap().simulate_at(1, position, None).waveforms.unwrap();
+        assert!(wfs.row(brightest).iter().all(|&v| v == 0.0));
+        assert!(wfs.sum() > 0.0);
+    }
+
+    #[test]
+    fn waveforms_match_the_image() {
+        crate::random::seed(41);
+        let conf    = SimConfig::new("preset:timing").unwrap();
+        let timing  = conf.timing.clone().map(|t| Timing{shaping_tau: 0.0, n_samples: 1000, ..t});
+        let dir     = tempfile::tempdir().unwrap();
+        let map     = dir.path().join("sipm_map.csv");
+        let n       = Simulator::new(&conf).unwrap().layout().n_channels();
+        let rows    = (0..n).map(|id| format!("{id}, 0.5\n")).collect::<String>();
+        std::fs::write(&map, format!("id, pde\n{rows}")).unwrap();
+        let calib   = Calibration::new(Some(map.to_str().unwrap().to_owned()), None, 0.0);
+        let conf    = SimConfig{timing, calibration: Some(calib), ..conf};
+
+        let event   = Simulator::new(&conf).unwrap().simulate_at(0, point!(1.0, 2.0), None);
+        let wfs     = event.waveforms.unwrap();
+        let img     = event.img.transpose();
+        assert!(event.n_detected > 0);
+        // the same photons are detected in both
+        for (ch, wf) in wfs.row_iter().enumerate() {
+            assert_eq!(wf.sum(), img.as_slice()[ch] as f64, "channel {ch}");
+        }
+    }
+
+    #[test]
+    fn point_like_cloud() {
+        // cloud_r omitted, so 0: every electron starts at the event position
+        let dir      = tempfile::tempdir().unwrap();
+        let path     = dir.path().join("point.toml");
+        let contents = std::fs::read_to_string("conf/test.toml").unwrap()
+                           .lines()
+                           .filter(|l| !l.starts_with("cloud_r"))
+                           .collect::<Vec<_>>()
+                           .join("\n");
+        std::fs::write(&path, contents).unwrap();
+        let conf     = SimConfig::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(conf.sim_params.cloud_r, 0.0);
+
+        let mut sim  = Simulator::new(&conf).unwrap();
+        let event    = sim.simulate_at(0, point!(1.0, 2.0), None);
+        assert!(event.n_electrons > 0);
+        assert_eq!(event.wire_q.iter().filter(|&&q| q > 0).count(), 1);
+    }
+
+    #[test]
+    fn images_reset_between_events() {
+        crate::random::seed(37);
+        let conf    = SimConfig::new("conf/test.toml").unwrap();
+        let mut sim = Simulator::new(&conf).unwrap();
+        let first   = sim.simulate_at(0, point!(0.0, 0.0), None).img.sum() as f64;
+        let second  = sim.simulate_at(1, point!(0.0, 0.0), None).img.sum() as f64;
+        // nearly Poisson: without a reset the second would double
+        assert!((second - first).abs() < 5.0 * (2.0 * first).sqrt(), "{first} then {second}");
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs::read_to_string;
+use std::io;
+use nalgebra::{point, Point2};
+
+/// Row of `n` cells of width `size` placed every `pitch`, centred at
+/// `offset`. Cells are half-open, [low, high).
+#[derive(Debug, Clone, Copy)]
+pub struct GridAxis {
+    n        : usize,
+    pitch    : f64,
+    inv_pitch: f64,
+    centre   : f64,
+    half_size: f64,
+    offset   : f64,
+}
+
+impl GridAxis {
+    pub(crate) fn new(n: usize, pitch: f64, size: f64, offset: f64) -> Self {
+        let centre = (n as f64 - 1.0) / 2.0;
+        Self{ n, pitch, inv_pitch: 1.0 / pitch, centre, half_size: size / 2.0, offset }
+    }
+
+    /// Index of the cell containing `u`. `None` if `u` falls between cells
+    /// or outside the row.
+    #[inline]
+    pub(crate) fn index(&self, u: f64) -> Option<usize> {
+        // Truncation instead of round/floor, which may not be inlined
+        let u = u - self.offset;
+        let v = u * self.inv_pitch + self.centre + 0.5;
+        if v < 0.0 { return None } // NaN is rejected by the range check below
+        let k = v as usize;
+        if k >= self.n { return None }
+        let d = u - (k as f64 - self.centre) * self.pitch;
+        if (-self.half_size..self.half_size).contains(&d) { Some(k) } else { None }
+    }
+
+    pub(crate) fn position(&self, k: usize) -> f64 {
+        (k as f64 - self.centre) * self.pitch + self.offset
+    }
+
+    /// Largest distance from the origin to the edge of the row, including
+    /// half a gap on each side.
+    fn extent(&self) -> f64 {
+        self.offset.abs() + self.n as f64 * self.pitch / 2.0
+    }
+}
+
+/// Rectangular sensor, centred at (`x`, `y`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensor {
+    pub x     : f64,
+    pub y     : f64,
+    pub size_x: f64,
+    pub size_y: f64,
+}
+
+impl Sensor {
+    fn contains(&self, p: &Point2<f64>) -> bool {
+        (self.x - self.size_x/2.0..self.x + self.size_x/2.0).contains(&p.x) &&
+        (self.y - self.size_y/2.0..self.y + self.size_y/2.0).contains(&p.y)
+    }
+}
+
+/// Arbitrary set of sensors. Lookups go through a uniform grid of cells, each
+/// listing the sensors that overlap it.
+#[derive(Debug, Clone)]
+pub struct SensorList {
+    sensors: Vec<Sensor>,
+    x0     : f64,
+    y0     : f64,
+    cell   : f64,
+    nx     : usize,
+    ny     : usize,
+    cells  : Vec<Vec<usize>>,
+}
+
+impl SensorList {
+    pub fn new(sensors: Vec<Sensor>) -> Self {
+        if sensors.is_empty() {
+            return Self{ sensors, x0: 0.0, y0: 0.0, cell: 1.0, nx: 0, ny: 0, cells: vec![] }
+        }
+        let x0   = sensors.iter().map(|s| s.x - s.size_x/2.0    ).fold(f64::INFINITY    , f64::min);
+        let y0   = sensors.iter().map(|s| s.y - s.size_y/2.0    ).fold(f64::INFINITY    , f64::min);
+        let x1   = sensors.iter().map(|s| s.x + s.size_x/2.0    ).fold(f64::NEG_INFINITY, f64::max);
+        let y1   = sensors.iter().map(|s| s.y + s.size_y/2.0    ).fold(f64::NEG_INFINITY, f64::max);
+        let cell = sensors.iter().map(|s| s.size_x.max(s.size_y)).fold(0.0              , f64::max);
+
+        let nx        = ((x1 - x0) / cell).ceil().max(1.0) as usize;
+        let ny        = ((y1 - y0) / cell).ceil().max(1.0) as usize;
+        let mut cells = vec![Vec::new(); nx * ny];
+        let clamp     = |v: f64, n: usize| (v.max(0.0) as usize).min(n - 1);
+        for (k, s) in sensors.iter().enumerate() {
+            let ix0 = clamp((s.x - s.size_x/2.0 - x0) / cell, nx);
+            let ix1 = clamp((s.x + s.size_x/2.0 - x0) / cell, nx);
+            let iy0 = clamp((s.y - s.size_y/2.0 - y0) / cell, ny);
+            let iy1 = clamp((s.y + s.size_y/2.0 - y0) / cell, ny);
+            for iy in iy0..=iy1 {
+                for ix in ix0..=ix1 {
+                    cells[iy * nx + ix].push(k);
+                }
+            }
+        }
+        Self{ sensors, x0, y0, cell, nx, ny, cells }
+    }
+
+    /// Reads a sensor list with columns x, y, size_x, size_y separated by
+    /// commas or whitespace. Lines starting with `#` and a non-numeric header
+    /// line are skipped. Channels are numbered in file order.
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        let contents = read_to_string(filename).map_err(|e| io::Error::new(e.kind(), format!("{filename}: {e}")))?;
+        let invalid  = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}: {msg}"));
+        let mut sensors = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let values : Result<Vec<f64>, _> =
+                line.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(str::parse)
+                    .collect();
+            match values {
+                Ok(v) if v.len() == 4 && !(v[2] > 0.0 && v[3] > 0.0) => return Err(invalid(format!("line {}: sensor sizes must be positive", i+1))),
+                Ok(v) if v.len() == 4 => sensors.push(Sensor{x: v[0], y: v[1], size_x: v[2], size_y: v[3]}),
+                Ok(v)                 => return Err(invalid(format!("line {}: expected 4 columns, found {}", i+1, v.len()))),
+                Err(_) if sensors.is_empty() => continue, // header
+                Err(e)                => return Err(invalid(format!("line {}: {e}", i+1))),
+            }
+        }
+        Ok(Self::new(sensors))
+    }
+
+    fn locate(&self, p: &Point2<f64>) -> Option<usize> {
+        let ix = (p.x - self.x0) / self.cell;
+        let iy = (p.y - self.y0) / self.cell;
+        if ix < 0.0 || iy < 0.0 { return None }
+        let (ix, iy) = (ix as usize, iy as usize);
+        if ix >= self.nx || iy >= self.ny { return None }
+        self.cells[iy * self.nx + ix]
+            .iter()
+            .copied()
+            .find(|&k| self.sensors[k].contains(p))
+    }
+
+    pub fn sensors(&self) -> &[Sensor] {
+        &self.sensors
+    }
+}
+
+/// Sensor arrangement of the SiPM plane, built from `SipmPlane::layout`.
+/// Channels are numbered row-major for grids (rows along y) and in file
+/// order for sensor lists. Images of a sensor list have a single row.
+#[derive(Debug, Clone)]
+pub enum SipmLayout {
+    Grid{ x: GridAxis, y: GridAxis },
+    List(SensorList),
+}
+
+impl SipmLayout {
+    /// Grid of `nx` x `ny` sensors of `size_x` x `size_y` separated by the
+    /// given gaps, centred at `offset`.
+    pub fn grid((nx, ny): (usize, usize), (size_x, size_y): (f64, f64), (gap_x, gap_y): (f64, f64), (offset_x, offset_y): (f64, f64)) -> Self {
+        let x = GridAxis::new(nx, size_x + gap_x, size_x, offset_x);
+        let y = GridAxis::new(ny, size_y + gap_y, size_y, offset_y);
+        Self::Grid{x, y}
+    }
+
+    /// Image dimensions, (rows, columns).
+    pub fn shape(&self) -> (usize, usize) {
+        match self {
+            Self::Grid{x, y} => (y.n, x.n),
+            Self::List(list) => (1, list.sensors.len()),
+        }
+    }
+
+    pub fn n_channels(&self) -> usize {
+        let (rows, cols) = self.shape();
+        rows * cols
+    }
+
+    /// (row, column) of the sensor containing `p`. `None` if `p` falls in a
+    /// gap or outside the plane.
+    #[inline]
+    pub fn locate(&self, p: &Point2<f64>) -> Option<(usize, usize)> {
+        match self {
+            Self::Grid{x, y} => Some((y.index(p.y)?, x.index(p.x)?)),
+            Self::List(list) => Some((0, list.locate(p)?)),
+        }
+    }
+
+    pub fn channel(&self, p: &Point2<f64>) -> Option<usize> {
+        let (row, col) = self.locate(p)?;
+        Some(row * self.shape().1 + col)
+    }
+
+    /// Sensor centres, by channel.
+    pub fn positions(&self) -> Vec<Point2<f64>> {
+        match self {
+            Self::Grid{x, y} =>
+                (0..y.n).flat_map(|i| (0..x.n).map(move |j| point!(x.position(j), y.position(i))))
+                        .collect(),
+            Self::List(list) =>
+                list.sensors.iter().map(|s| point!(s.x, s.y)).collect(),
+        }
+    }
+
+    /// Sensors, by channel.
+    pub fn sensors(&self) -> Vec<Sensor> {
+        match self {
+            Self::Grid{x, y} =>
+                self.positions()
+                    .into_iter()
+                    .map(|p| Sensor{x: p.x, y: p.y, size_x: 2.0 * x.half_size, size_y: 2.0 * y.half_size})
+                    .collect(),
+            Self::List(list) => list.sensors.clone(),
+        }
+    }
+
+    /// Half-width of the smallest square centred at the origin containing
+    /// every sensor.
+    pub fn extent(&self) -> f64 {
+        match self {
+            Self::Grid{x, y} => x.extent().max(y.extent()),
+            Self::List(list) =>
+                list.sensors.iter()
+                    .map (|s| (s.x.abs() + s.size_x/2.0).max(s.y.abs() + s.size_y/2.0))
+                    .fold(0.0, f64::max),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use pretty_assertions::assert_eq;
+    use float_eq::assert_float_eq;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn rectangular_grid() {
+        let layout = SipmLayout::grid((3, 2), (1.0, 2.0), (0.5, 1.0), (0.0, 0.0));
+        assert_eq!(layout.shape(), (2, 3));
+        assert_eq!(layout.locate(&point!(-1.5, -1.5)), Some((0, 0)));
+        assert_eq!(layout.locate(&point!( 1.5,  1.5)), Some((1, 2)));
+        assert_eq!(layout.locate(&point!( 0.0,  0.0)), None); // y gap
+        assert_eq!(layout.locate(&point!( 0.7,  1.5)), None); // x gap
+        assert_eq!(layout.channel(&point!( 1.5,  1.5)), Some(5));
+        assert_float_eq!(layout.extent(), 3.0, ulps<=2);
+    }
+
+    #[test]
+    fn offset_grid() {
+        let layout = SipmLayout::grid((2, 2), (1.0, 1.0), (0.0, 0.0), (10.0, -5.0));
+        assert_eq!(layout.locate(&point!( 9.5, -5.5)), Some((0, 0)));
+        assert_eq!(layout.locate(&point!(10.5, -4.5)), Some((1, 1)));
+        assert_eq!(layout.locate(&point!( 0.0,  0.0)), None);
+        let pos = layout.positions();
+        assert_eq!(pos[0], point!( 9.5, -5.5));
+        assert_eq!(pos[3], point!(10.5, -4.5));
+    }
+
+    #[test]
+    fn sensors_match_channels() {
+        let layout = SipmLayout::grid((4, 3), (2.0, 1.0), (0.2, 0.4), (0.3, -0.1));
+        for (ch, s) in layout.sensors().iter().enumerate() {
+            assert_eq!((s.size_x, s.size_y), (2.0, 1.0));
+            assert_eq!(layout.channel(&point!(s.x + 0.99, s.y - 0.49)), Some(ch));
+        }
+    }
+
+    #[test]
+    fn positions_match_channels() {
+        let layout = SipmLayout::grid((4, 3), (2.0, 1.0), (0.2, 0.4), (0.3, -0.1));
+        for (ch, p) in layout.positions().iter().enumerate() {
+            assert_eq!(layout.channel(p), Some(ch));
+        }
+    }
+
+    #[test]
+    fn sensor_list() {
+        let sensors = vec![
+            Sensor{x: 0.0, y: 0.0, size_x: 1.0, size_y: 1.0},
+            Sensor{x: 3.0, y: 0.0, size_x: 2.0, size_y: 1.0},
+            Sensor{x: 0.0, y: 5.0, size_x: 1.0, size_y: 3.0},
+        ];
+        let layout = SipmLayout::List(SensorList::new(sensors));
+        assert_eq!(layout.shape(), (1, 3));
+        assert_eq!(layout.channel(&point!( 0.2,  0.2)), Some(0));
+        assert_eq!(layout.channel(&point!( 3.9, -0.4)), Some(1));
+        assert_eq!(layout.channel(&point!(-0.4,  6.4)), Some(2));
+        assert_eq!(layout.channel(&point!( 1.5,  0.0)), None);
+        assert_eq!(layout.channel(&point!( 3.0,  5.0)), None); // missing sensor
+        assert_float_eq!(layout.extent(), 6.5, ulps<=2);
+    }
+
+    #[test]
+    fn sensor_list_from_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "x,y,size_x,size_y").unwrap();
+        writeln!(file, "# dead sensor removed").unwrap();
+        writeln!(file, "-1.0, 0.0, 1.0, 1.0").unwrap();
+        writeln!(file, " 1.0  0.0  1.0  1.0").unwrap();
+        let list = SensorList::from_file(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(list.sensors().len(), 2);
+        assert_eq!(list.sensors()[1], Sensor{x: 1.0, y: 0.0, size_x: 1.0, size_y: 1.0});
+    }
+
+    #[test]
+    fn sensor_list_bad_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "1.0, 0.0, 1.0").unwrap();
+        assert!(SensorList::from_file(file.path().to_str().unwrap()).is_err());
+
+        for sizes in ["0.0, 1.0", "1.0, -1.0", "NaN, 1.0"] {
+            let mut file = NamedTempFile::new().unwrap();
+            writeln!(file, "1.0, 0.0, {sizes}").unwrap();
+            let err = SensorList::from_file(file.path().to_str().unwrap()).unwrap_err();
+            assert!(err.to_string().contains("sizes must be positive"), "{err}");
+        }
+    }
+}
@@ -1,23 +1,98 @@
+use std::io;
+use std::sync::OnceLock;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+use crate::sipm_layout::{SipmLayout, SensorList};
+use crate::units;
+
+/// SiPM plane. Either a grid of square sensors of `sipm_size`, given by
+/// `n_sipms_side` or by `n_sipms_x`/`n_sipms_y` (optionally with per-axis
+/// gaps and an offset), or an explicit list of sensors read from
+/// `layout_file`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct SipmPlane {
+    /// Side of each sensor, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub sipm_size   : f64,
+    /// Active area of each sensor, mm²
+    #[serde(deserialize_with = "units::area")]
+    #[schemars(with = "units::Quantity")]
     pub sipm_area   : f64,
+    /// Distance between neighbouring sensors, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub sipm_gap    : f64,
-    pub n_sipms_side: usize,
+    /// Sensors along each side of a square grid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_sipms_side: Option<usize>,
+    /// Sensors along x, `n_sipms_side` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_sipms_x   : Option<usize>,
+    /// Sensors along y, `n_sipms_side` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_sipms_y   : Option<usize>,
+    /// Gap along x, `sipm_gap` by default
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "units::optional_length")]
+    #[schemars(with = "Option<units::Quantity>")]
+    pub sipm_gap_x  : Option<f64>,
+    /// Gap along y, `sipm_gap` by default
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "units::optional_length")]
+    #[schemars(with = "Option<units::Quantity>")]
+    pub sipm_gap_y  : Option<f64>,
+    /// Position of the grid centre along x, mm
+    #[serde(default, deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
+    pub offset_x    : f64,
+    /// Position of the grid centre along y, mm
+    #[serde(default, deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
+    pub offset_y    : f64,
+    /// CSV file with x, y, size_x, size_y per sensor, relative to the
+    /// configuration file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout_file : Option<String>,
+    /// Sensors read from `layout_file`, with its name, so that the file is
+    /// read once
+    #[serde(skip)]
+    #[schemars(skip)]
+    sensors         : OnceLock<(String, SensorList)>,
 }
 
 
 impl SipmPlane {
+    /// Square grid of `n_sipms_side` x `n_sipms_side` sensors centred at the
+    /// origin.
+    pub fn square(sipm_size: f64, sipm_area: f64, sipm_gap: f64, n_sipms_side: usize) -> Self {
+        Self{ sipm_size, sipm_area, sipm_gap
+            , n_sipms_side: Some(n_sipms_side)
+            , n_sipms_x   : None
+            , n_sipms_y   : None
+            , sipm_gap_x  : None
+            , sipm_gap_y  : None
+            , offset_x    : 0.0
+            , offset_y    : 0.0
+            , layout_file : None
+            , sensors     : OnceLock::new()
+            }
+    }
+
+    pub fn n_x(&self) -> usize { self.n_sipms_x.or(self.n_sipms_side).unwrap_or(0) }
+    pub fn n_y(&self) -> usize { self.n_sipms_y.or(self.n_sipms_side).unwrap_or(0) }
+
+    pub fn gap_x(&self) -> f64 { self.sipm_gap_x.unwrap_or(self.sipm_gap) }
+    pub fn gap_y(&self) -> f64 { self.sipm_gap_y.unwrap_or(self.sipm_gap) }
+
     pub fn sipm_pitch(&self) -> f64 {
         self.sipm_size + self.sipm_gap
     }
 
+    /// Sensor centres along x of a grid centred at the origin.
     pub fn sipm_pos(&self) -> Vec<f64> {
-        let n = self.n_sipms_side/2;
+        let n = self.n_x()/2;
         let mut v : Vec<f64> =
-            (0..n).into_iter()
+            (0..n)
                   .map      (|i| (i as f64  + 0.5) * self.sipm_pitch())
                   .flat_map (|p| [-p, p].into_iter())
                   .collect  ();
@@ -25,6 +100,7 @@ impl SipmPlane {
         v
     }
 
+    /// Bin edges along x of a grid centred at the origin, including the gaps.
     pub fn sipm_bins(&self) -> Vec<f64> {
         let v = self.sipm_pos();
         let inner_bins = [ -self.sipm_size/2. - self.sipm_gap/2.
@@ -37,20 +113,40 @@ impl SipmPlane {
         bins.push(bins.last().unwrap() + self.sipm_size/2. + self.sipm_gap/2.);
         bins
     }
+
+    /// Builds the sensor layout, reading `layout_file` if given.
+    pub fn layout(&self) -> io::Result<SipmLayout> {
+        if let Some(filename) = &self.layout_file {
+            let sensors = match self.sensors.get() {
+                Some((name, sensors)) if name == filename => sensors.clone(),
+                _ => {
+                    let sensors = SensorList::from_file(filename)?;
+                    let _       = self.sensors.set((filename.clone(), sensors.clone()));
+                    sensors
+                }
+            };
+            return Ok(SipmLayout::List(sensors))
+        }
+        if self.n_x() == 0 || self.n_y() == 0 {
+            let msg = "sipm_plane needs n_sipms_side, n_sipms_x and n_sipms_y or layout_file";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+        }
+        Ok(SipmLayout::grid( (self.n_x(), self.n_y())
+                           , (self.sipm_size, self.sipm_size)
+                           , (self.gap_x(), self.gap_y())
+                           , (self.offset_x, self.offset_y)))
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use float_eq::assert_float_eq;
+    use nalgebra::point;
     use super::*;
 
     fn test_plane() -> SipmPlane {
-        SipmPlane{ sipm_size: 6.0
-                 , sipm_area: 5.85 * 5.95
-                 , sipm_gap: 0.5
-                 , n_sipms_side: 10
-                 }
+        SipmPlane::square(6.0, 5.85 * 5.95, 0.5, 10)
     }
 
     #[test]
@@ -64,4 +160,55 @@ mod tests {
         assert_float_eq!(               pos[5],  plane.sipm_pitch()/2., ulps<=2);
 
     }
+
+    #[test]
+    fn sipm_index() {
+        let plane  = test_plane();
+        let layout = plane.layout().unwrap();
+        let pos    = plane.sipm_pos();
+        for (i, &y) in pos.iter().enumerate() {
+            for (j, &x) in pos.iter().enumerate() {
+                let p = point!(x + 2.9, y - 2.9);
+                assert_eq!(layout.locate(&p), Some((i, j)));
+            }
+        }
+        assert_eq!(layout.locate(&point!( 0.0 ,  1.0)), None); // gap
+        assert_eq!(layout.locate(&point!( 1.0 , 32.6)), None); // outside
+        assert_eq!(layout.locate(&point!(-32.0, 32.0)), Some((9, 0)));
+    }
+
+    #[test]
+    fn sipm_index_matches_bins() {
+        let plane  = test_plane();
+        let layout = plane.layout().unwrap();
+        let bins   = plane.sipm_bins();
+        for k in 0..plane.n_x() {
+            let lo = bins[3*k + 1];
+            let hi = bins[3*k + 2];
+            let x  = (lo + hi) / 2.0;
+            assert_eq!(layout.locate(&point!(x, x)), Some((k, k)));
+        }
+    }
+
+    #[test]
+    fn rectangular_layout() {
+        let plane = SipmPlane{ n_sipms_side: None
+                             , n_sipms_x   : Some(4)
+                             , n_sipms_y   : Some(2)
+                             , sipm_gap_y  : Some(1.0)
+                             , offset_x    : 3.0
+                             , ..test_plane()
+                             };
+        let layout = plane.layout().unwrap();
+        assert_eq!(layout.shape(), (2, 4));
+        assert_eq!(layout.locate(&point!(3.0 + 9.75, 3.5)), Some((1, 3)));
+        assert_eq!(layout.locate(&point!(3.0 + 9.75, 0.2)), None);
+    }
+
+    #[test]
+    fn layout_needs_dimensions() {
+        let plane = SipmPlane{n_sipms_side: None, n_sipms_x: Some(4), ..test_plane()};
+        assert!(plane.layout().is_err());
+    }
 }
+
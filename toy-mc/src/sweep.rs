@@ -0,0 +1,175 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use config::{ConfigError, ValueKind};
+use derive_new::new;
+
+/// Value of a single configuration entry.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Scalar {
+    Int  (i64),
+    Float(f64),
+    Bool (bool),
+    Text (String),
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scalar::Int  (v) => write!(f, "{v}"),
+            Scalar::Float(v) => write!(f, "{v}"),
+            Scalar::Bool (v) => write!(f, "{v}"),
+            Scalar::Text (v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl From<Scalar> for ValueKind {
+    fn from(value: Scalar) -> Self {
+        match value {
+            Scalar::Int  (v) => v.into(),
+            Scalar::Float(v) => v.into(),
+            Scalar::Bool (v) => v.into(),
+            Scalar::Text (v) => v.into(),
+        }
+    }
+}
+
+/// `n` values evenly spaced from `start` to `stop`, both included.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Range {
+    /// First value
+    pub start: f64,
+    /// Last value
+    pub stop : f64,
+    /// Number of values
+    pub n    : usize,
+}
+
+impl Range {
+    pub fn values(&self) -> Vec<f64> {
+        let step = if self.n > 1 { (self.stop - self.start) / (self.n - 1) as f64 } else { 0.0 };
+        (0..self.n).map(|i| self.start + i as f64 * step).collect()
+    }
+}
+
+/// Configuration entry, as a dotted path (`geometry.wire_plane.wire_pitch`),
+/// and the values it takes: the listed ones followed by the range.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Parameter {
+    /// Dotted path of the entry
+    pub key   : String,
+    /// Values taken, before those of `range`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<Scalar>,
+    /// Evenly spaced values taken
+    #[serde(default)]
+    pub range : Option<Range>,
+}
+
+impl Parameter {
+    pub fn values(&self) -> Vec<Scalar> {
+        let range = self.range.iter().flat_map(Range::values).map(Scalar::Float);
+        self.values.iter().cloned().chain(range).collect()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SweepMode {
+    /// Every combination of the values of all parameters
+    #[default]
+    Product,
+    /// The i-th value of every parameter together
+    Zip,
+}
+
+/// Set of runs differing in a few configuration entries.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Sweep {
+    /// How the values of the parameters combine into points, `product` by
+    /// default
+    #[serde(default)]
+    pub mode      : SweepMode,
+    /// Entries varied, each with its values
+    #[serde(rename = "parameter")]
+    pub parameters: Vec<Parameter>,
+}
+
+/// Values of the swept parameters in one run.
+pub type Point = Vec<(String, Scalar)>;
+
+impl Sweep {
+    pub fn points(&self) -> Result<Vec<Point>, ConfigError> {
+        let values : Vec<Vec<Scalar>> = self.parameters.iter().map(Parameter::values).collect();
+        if let Some(p) = self.parameters.iter().zip(&values).find(|(_, v)| v.is_empty()) {
+            return Err(ConfigError::Message(format!("sweep parameter {} has no values", p.0.key)))
+        }
+        let keys = self.parameters.iter().map(|p| p.key.clone());
+        match self.mode {
+            SweepMode::Product => {
+                let points = values.iter().fold(vec![vec![]], |points: Vec<Vec<Scalar>>, vs| {
+                    points.iter()
+                          .flat_map(|p| vs.iter().map(move |v| { let mut p = p.clone(); p.push(v.clone()); p }))
+                          .collect()
+                });
+                Ok(points.into_iter().map(|p| keys.clone().zip(p).collect()).collect())
+            }
+            SweepMode::Zip => {
+                let n = values.first().map_or(0, Vec::len);
+                if values.iter().any(|v| v.len() != n) {
+                    return Err(ConfigError::Message("zipped sweep parameters have different lengths".to_owned()))
+                }
+                Ok((0..n).map(|i| keys.clone().zip(values.iter().map(|v| v[i].clone())).collect()).collect())
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn sweep(mode: SweepMode) -> Sweep {
+        Sweep::new(mode, vec![
+            Parameter::new("a".to_owned(), vec![Scalar::Int(1), Scalar::Int(2)], None),
+            Parameter::new("b".to_owned(), vec![], Some(Range::new(0.0, 1.0, 2))),
+        ])
+    }
+
+    fn values(points: &[Point]) -> Vec<Vec<String>> {
+        points.iter().map(|p| p.iter().map(|(_, v)| v.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn range_includes_ends() {
+        assert_eq!(Range::new(1.0, 2.0, 5).values(), vec![1.0, 1.25, 1.5, 1.75, 2.0]);
+        assert_eq!(Range::new(1.0, 2.0, 1).values(), vec![1.0]);
+    }
+
+    #[test]
+    fn product() {
+        let points = sweep(SweepMode::Product).points().unwrap();
+        assert_eq!(values(&points), [["1", "0"], ["1", "1"], ["2", "0"], ["2", "1"]]);
+        assert_eq!(points[0][1].0, "b");
+    }
+
+    #[test]
+    fn zip() {
+        let points = sweep(SweepMode::Zip).points().unwrap();
+        assert_eq!(values(&points), [["1", "0"], ["2", "1"]]);
+    }
+
+    #[test]
+    fn invalid_sweeps() {
+        let mut s = sweep(SweepMode::Zip);
+        s.parameters[0].values.push(Scalar::Int(3));
+        assert!(s.points().is_err());
+        s.parameters[1].range = None;
+        s.mode = SweepMode::Product;
+        assert!(s.points().is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use derive_new::new;
+use nalgebra::DMatrix;
+
+/// Time response of the detector. All times in ns.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Timing {
+    /// Mean arrival time of the electrons at the wires
+    pub drift_time    : f64,
+    /// Spread of the arrival times
+    pub long_diffusion: f64,
+    /// Decay time of the EL emission
+    pub el_tau        : f64,
+    /// Width of each waveform sample
+    pub bin_width     : f64,
+    /// Samples per waveform
+    pub n_samples     : usize,
+    /// Shaping time of the electronics, none if 0
+    pub shaping_tau   : f64,
+}
+
+impl Timing {
+    /// Checks that the waveforms have samples of positive width.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bin_width.is_nan() || self.bin_width <= 0.0 {
+            return Err(format!("timing.bin_width must be positive, found {}", self.bin_width))
+        }
+        if self.n_samples == 0 {
+            return Err("timing.n_samples must be at least 1".to_owned())
+        }
+        Ok(())
+    }
+
+    /// Samples the photon arrival times of each channel into `n_channels`
+    /// waveforms of `n_samples` bins. Photons beyond the sampling window are
+    /// dropped.
+    pub fn sample(&self, hits: &[(usize, f64)], n_channels: usize) -> DMatrix<f64> {
+        let mut wfs = DMatrix::zeros(n_channels, self.n_samples);
+        for &(channel, t) in hits {
+            if t < 0.0 { continue }
+            let bin = (t / self.bin_width).floor() as usize;
+            if bin < self.n_samples { wfs[(channel, bin)] += 1.0; }
+        }
+        self.shape(wfs)
+    }
+
+    /// Convolves each waveform with a unit-area exponential of constant
+    /// `shaping_tau`. The kernel is truncated at the end of the window.
+    pub fn shape(&self, wfs: DMatrix<f64>) -> DMatrix<f64> {
+        if self.shaping_tau <= 0.0 { return wfs }
+
+        let n        = self.n_samples;
+        let decay    = (-self.bin_width / self.shaping_tau).exp();
+        let kernel   : Vec<f64> = (0..n).map(|i| decay.powi(i as i32)).collect();
+        let norm     : f64      = kernel.iter().sum();
+        let mut out  = DMatrix::zeros(wfs.nrows(), n);
+        for (ch, row) in wfs.row_iter().enumerate() {
+            for (i, &q) in row.iter().enumerate() {
+                if q == 0.0 { continue }
+                for (j, k) in kernel[..n-i].iter().enumerate() {
+                    out[(ch, i+j)] += q * k / norm;
+                }
+            }
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn test_timing(shaping_tau: f64) -> Timing {
+        Timing::new(1000.0, 0.0, 0.0, 10.0, 50, shaping_tau)
+    }
+
+    #[test]
+    fn sampling_bins() {
+        let timing = test_timing(0.0);
+        let hits   = [(0, 5.0), (0, 15.0), (0, 19.9), (1, 25.0), (1, 1e4), (1, -1.0)];
+        let wfs    = timing.sample(&hits, 2);
+        assert_eq!(wfs.shape(), (2, 50));
+        assert_float_eq!(wfs[(0, 0)], 1.0, ulps<=2);
+        assert_float_eq!(wfs[(0, 1)], 2.0, ulps<=2);
+        assert_float_eq!(wfs[(1, 2)], 1.0, ulps<=2);
+        assert_float_eq!(wfs.sum()  , 4.0, ulps<=2);
+    }
+
+    #[test]
+    fn shaping_preserves_area() {
+        let timing = test_timing(20.0);
+        let hits   = [(0, 5.0), (0, 15.0), (0, 19.9)];
+        let wfs    = timing.sample(&hits, 1);
+        assert_float_eq!(wfs.sum(), 3.0, abs<=1e-9);
+        assert!(wfs[(0, 1)] > wfs[(0, 5)]);
+        assert!(wfs[(0, 5)] > 0.0);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(test_timing(0.0).validate().is_ok());
+        let bad = [ Timing{bin_width: 0.0     , ..test_timing(0.0)}
+                  , Timing{bin_width: -1.0    , ..test_timing(0.0)}
+                  , Timing{bin_width: f64::NAN, ..test_timing(0.0)}
+                  , Timing{n_samples: 0       , ..test_timing(0.0)}
+                  ];
+        for timing in bad {
+            assert!(timing.validate().is_err(), "{timing:?}");
+        }
+    }
+
+    #[test]
+    fn shaping_is_causal() {
+        let timing = test_timing(20.0);
+        let wfs    = timing.sample(&[(0, 105.0)], 1);
+        for i in 0..10 {
+            assert_float_eq!(wfs[(0, i)], 0.0, ulps<=2);
+        }
+        assert!(wfs[(0, 10)] > 0.0);
+    }
+}
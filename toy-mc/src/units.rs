@@ -0,0 +1,176 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+use schemars::JsonSchema;
+
+/// Dimension of a configuration value. Plain numbers are taken in the
+/// canonical unit: mm, mm², eV, degrees, bar and kV/cm.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dimension {
+    Length,
+    Area,
+    Energy,
+    Angle,
+    Pressure,
+    Field,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Dimension::Length   => "a length",
+            Dimension::Area     => "an area",
+            Dimension::Energy   => "an energy",
+            Dimension::Angle    => "an angle",
+            Dimension::Pressure => "a pressure",
+            Dimension::Field    => "a field",
+        };
+        write!(f, "{name}")
+    }
+}
+
+use Dimension::*;
+
+/// Known units and their value in the canonical unit of their dimension.
+const UNITS: [(&str, Dimension, f64); 31] = [
+    ("nm"   , Length  , 1e-6),
+    ("um"   , Length  , 1e-3),
+    ("µm"   , Length  , 1e-3),
+    ("mm"   , Length  , 1.0),
+    ("cm"   , Length  , 10.0),
+    ("m"    , Length  , 1e3),
+    ("um2"  , Area    , 1e-6),
+    ("µm2"  , Area    , 1e-6),
+    ("mm2"  , Area    , 1.0),
+    ("mm²"  , Area    , 1.0),
+    ("cm2"  , Area    , 1e2),
+    ("cm²"  , Area    , 1e2),
+    ("meV"  , Energy  , 1e-3),
+    ("eV"   , Energy  , 1.0),
+    ("keV"  , Energy  , 1e3),
+    ("MeV"  , Energy  , 1e6),
+    ("GeV"  , Energy  , 1e9),
+    ("deg"  , Angle   , 1.0),
+    ("°"    , Angle   , 1.0),
+    ("rad"  , Angle   , 180.0 / std::f64::consts::PI),
+    ("mrad" , Angle   , 0.18  / std::f64::consts::PI),
+    ("mbar" , Pressure, 1e-3),
+    ("bar"  , Pressure, 1.0),
+    ("atm"  , Pressure, 1.01325),
+    ("Pa"   , Pressure, 1e-5),
+    ("kPa"  , Pressure, 1e-2),
+    ("MPa"  , Pressure, 10.0),
+    ("V/cm" , Field   , 1e-3),
+    ("kV/cm", Field   , 1.0),
+    ("V/mm" , Field   , 1e-2),
+    ("kV/mm", Field   , 10.0),
+];
+
+/// Parses a value such as `5 um` or `41.5keV` into the canonical unit of
+/// `dimension`. A value without unit is already canonical.
+pub fn parse(text: &str, dimension: Dimension) -> Result<f64, String> {
+    let text   = text.trim();
+    let number = |s: &str| s.trim().parse::<f64>().map_err(|_| format!("invalid quantity \"{text}\""));
+    // The longest matching unit, so that mm is not read as m
+    let unit   = UNITS.iter()
+                      .filter(|(u, _, _)| text.ends_with(u))
+                      .max_by_key(|(u, _, _)| u.len());
+    match unit {
+        None                                   => number(text),
+        Some(&(u, dim, _)) if dim != dimension => Err(format!("expected {dimension}, found \"{text}\" ({dim}, {u})")),
+        Some(&(u, _, scale))                   => Ok(number(&text[..text.len() - u.len()])? * scale),
+    }
+}
+
+/// Value in the canonical unit of its dimension, or with units as a string.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Quantity {
+    Number(f64),
+    Text  (String),
+}
+
+impl Quantity {
+    /// Value in the canonical unit of `dimension`.
+    pub fn value(self, dimension: Dimension) -> Result<f64, String> {
+        match self {
+            Quantity::Number(v) => Ok(v),
+            Quantity::Text  (t) => parse(&t, dimension),
+        }
+    }
+}
+
+fn quantity<'de, D: Deserializer<'de>>(d: D, dimension: Dimension) -> Result<f64, D::Error> {
+    Quantity::deserialize(d)?.value(dimension).map_err(D::Error::custom)
+}
+
+pub fn length  <'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> { quantity(d, Length  ) }
+pub fn area    <'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> { quantity(d, Area    ) }
+pub fn energy  <'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> { quantity(d, Energy  ) }
+pub fn angle   <'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> { quantity(d, Angle   ) }
+pub fn pressure<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> { quantity(d, Pressure) }
+
+pub fn optional_length<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    length(d).map(Some)
+}
+
+pub fn optional_field<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    quantity(d, Field).map(Some)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn canonical_units() {
+        assert_float_eq!(parse("5 um"    , Length  ).unwrap(), 5e-3   , rmax<=1e-12);
+        assert_float_eq!(parse("5um"     , Length  ).unwrap(), 5e-3   , rmax<=1e-12);
+        assert_float_eq!(parse("3.2 cm"  , Length  ).unwrap(), 32.0   , rmax<=1e-12);
+        assert_float_eq!(parse("1e-3 m"  , Length  ).unwrap(), 1.0    , rmax<=1e-12);
+        assert_float_eq!(parse("41.5 keV", Energy  ).unwrap(), 41500.0, rmax<=1e-12);
+        assert_float_eq!(parse("34.8 mm2", Area    ).unwrap(), 34.8   , rmax<=1e-12);
+        assert_float_eq!(parse("45 deg"  , Angle   ).unwrap(), 45.0   , rmax<=1e-12);
+        assert_float_eq!(parse("1 rad"   , Angle   ).unwrap(), 57.29577951308232, rmax<=1e-12);
+        assert_float_eq!(parse(" 7.5 "   , Length  ).unwrap(), 7.5    , rmax<=1e-12);
+        assert_float_eq!(parse("1 atm"   , Pressure).unwrap(), 1.01325, rmax<=1e-12);
+        assert_float_eq!(parse("1.5 MPa" , Pressure).unwrap(), 15.0   , rmax<=1e-12);
+        assert_float_eq!(parse("500 V/cm", Field   ).unwrap(), 0.5    , rmax<=1e-12);
+    }
+
+    #[test]
+    fn wrong_dimension() {
+        let err = parse("41.5 keV", Length).unwrap_err();
+        assert!(err.contains("expected a length"), "{err}");
+        assert!(parse("5 mm" , Area ).is_err());
+        assert!(parse("5 deg", Energy).is_err());
+        assert!(parse("5 kV/cm", Length).is_err());
+    }
+
+    #[test]
+    fn invalid_quantity() {
+        assert!(parse("five mm", Length).is_err());
+        assert!(parse("5 parsecs", Length).is_err());
+        assert!(parse("", Length).is_err());
+    }
+
+    #[test]
+    fn deserialize_numbers_and_strings() {
+        #[derive(Deserialize)]
+        struct T {
+            #[serde(deserialize_with = "length")]
+            a: f64,
+            #[serde(deserialize_with = "length")]
+            b: f64,
+            #[serde(default, deserialize_with = "optional_length")]
+            c: Option<f64>,
+            #[serde(default, deserialize_with = "optional_length")]
+            d: Option<f64>,
+        }
+        let t : T = toml::from_str("a = 2\nb = \"2 cm\"\nc = \"10 um\"").unwrap();
+        assert_eq!((t.a, t.b, t.c, t.d), (2.0, 20.0, Some(0.01), None));
+        assert!(toml::from_str::<T>("a = \"2 eV\"\nb = 1").is_err());
+    }
+}
@@ -1,10 +1,24 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use nalgebra::Rotation2;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+use crate::units;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
 pub struct WirePlane {
+    /// Distance between wires, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub wire_pitch   : f64,
+    /// Wire radius, mm
+    #[serde(deserialize_with = "units::length")]
+    #[schemars(with = "units::Quantity")]
     pub wire_r       : f64,
+    /// Angle of the wires in the SiPM frame, degrees. Aligned by default
+    #[serde(default, deserialize_with = "units::angle")]
+    #[schemars(with = "units::Quantity")]
     pub wire_rotation: f64,
+    /// Number of wires, centred at the origin
     pub n_wires      : usize,
 }
 
@@ -12,13 +26,19 @@ impl WirePlane {
     pub fn wire_pos(&self) -> Vec<f64> {
         let n = self.n_wires/2;
         let mut v : Vec<f64> =
-            (0..n).into_iter()
+            (0..n)
                   .map      (|i| (i as f64 + 0.5) * self.wire_pitch)
                   .flat_map (|p| [-p, p].into_iter())
                   .collect  ();
         v.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
         v
     }
+
+    /// Rotation from the wire frame, where events are generated, to the
+    /// SiPM frame. `wire_rotation` is in degrees.
+    pub fn rotation(&self) -> Rotation2<f64> {
+        Rotation2::new(-self.wire_rotation.to_radians())
+    }
 }
 
 
@@ -47,4 +67,12 @@ mod tests {
         assert_float_eq!(               pos[7], plane.wire_pitch/2., ulps<=2);
     }
 
+    #[test]
+    fn rotation_in_degrees() {
+        let plane = WirePlane{wire_rotation: 90.0, ..test_plane()};
+        let p     = plane.rotation() * nalgebra::point!(1.0, 0.0);
+        assert_float_eq!(p.x,  0.0, abs<=1e-12);
+        assert_float_eq!(p.y, -1.0, abs<=1e-12);
+    }
+
 }
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use derive_new::new;
+
+use crate::ChannelMap;
+use crate::random::normal;
+
+/// Wire electronics. The collected charge is multiplied by `gain`, a fraction
+/// `induction[k]` of it is shared with each of the neighbours at distance
+/// k+1, `noise` (in electrons) is added and the result is digitized with
+/// `electrons_per_adc` into `adc_bits`. Values below `threshold` (ADC counts)
+/// are suppressed.
+#[derive(new, Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct WireReadout {
+    /// Amplification of the collected charge, 1 by default
+    #[serde(default = "one")]
+    pub gain             : f64,
+    /// Fraction shared with the neighbours at distance 1, 2, ...
+    #[serde(default)]
+    pub induction        : Vec<f64>,
+    /// Spread of the noise added, electrons
+    #[serde(default)]
+    pub noise            : f64,
+    /// Smallest value kept, ADC counts
+    #[serde(default)]
+    pub threshold        : usize,
+    /// Electrons per ADC count, 1 by default
+    #[serde(default = "one")]
+    pub electrons_per_adc: f64,
+    /// Resolution of the ADC, which saturates at `2^adc_bits - 1`, 16 by
+    /// default
+    #[serde(default = "default_bits")]
+    pub adc_bits         : u32,
+}
+
+fn one         () -> f64 {  1.0 }
+fn default_bits() -> u32 { 16   }
+
+impl Default for WireReadout {
+    fn default() -> Self {
+        Self::new(one(), vec![], 0.0, 0, one(), default_bits())
+    }
+}
+
+impl WireReadout {
+    /// Checks that the induction fractions leave a non-negative share on the
+    /// collecting wire and that the ADC conversion is positive.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(f) = self.induction.iter().find(|f| !(0.0..).contains(*f)) {
+            return Err(format!("wire_readout.induction fractions must not be negative, found {f}"))
+        }
+        let shared = self.induction.iter().sum::<f64>();
+        if shared > 0.5 {
+            return Err(format!("wire_readout.induction fractions add up to {shared}, above 0.5 shared on each side"))
+        }
+        if self.electrons_per_adc.is_nan() || self.electrons_per_adc <= 0.0 {
+            return Err(format!("wire_readout.electrons_per_adc must be positive, found {}", self.electrons_per_adc))
+        }
+        Ok(())
+    }
+
+    /// Charge seen by each wire after gain and induction sharing, in
+    /// electrons. The charge shared beyond the outermost wires is lost.
+    pub fn share(&self, q: &[usize], calib: Option<&ChannelMap>) -> Vec<f64> {
+        let n      = q.len();
+        let kept   = 1.0 - 2.0 * self.induction.iter().sum::<f64>();
+        let mut s  = vec![0.0; n];
+        for (w, &qw) in q.iter().enumerate() {
+            let gain = self.gain * calib.map_or(1.0, |c| c.get(w).gain);
+            let qw   = qw as f64 * gain;
+            s[w] += kept * qw;
+            for (k, f) in self.induction.iter().enumerate() {
+                let d = k + 1;
+                if w >= d    { s[w - d] += f * qw; }
+                if w + d < n { s[w + d] += f * qw; }
+            }
+        }
+        s
+    }
+
+    /// Digitized wire signals. Dead wires (per `calib`) read zero.
+    pub fn digitize(&self, q: &[usize], calib: Option<&ChannelMap>) -> Vec<usize> {
+        let max_adc = 1u64.checked_shl(self.adc_bits).map_or(u64::MAX, |v| v - 1) as f64;
+        self.share(q, calib)
+            .into_iter()
+            .enumerate()
+            .map(|(w, s)| {
+                if calib.is_some_and(|c| !c.get(w).alive) { return 0 }
+                let s   = if self.noise > 0.0 { normal(s, self.noise) } else { s };
+                let adc = (s / self.electrons_per_adc).round().clamp(0.0, max_adc) as usize;
+                if adc < self.threshold { 0 } else { adc }
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use float_eq::assert_float_eq;
+    use crate::ChannelCalib;
+
+    #[test]
+    fn ideal() {
+        let readout = WireReadout::default();
+        assert_eq!(readout.digitize(&[0, 3, 100], None), vec![0, 3, 100]);
+    }
+
+    #[test]
+    fn induction_sharing() {
+        let readout = WireReadout{induction: vec![0.1, 0.05], ..Default::default()};
+        let s       = readout.share(&[0, 0, 100, 0, 0], None);
+        let expected = [5.0, 10.0, 70.0, 10.0, 5.0];
+        for (got, exp) in s.iter().zip(expected) {
+            assert_float_eq!(*got, exp, abs<=1e-9);
+        }
+    }
+
+    #[test]
+    fn induction_lost_at_edges() {
+        let readout = WireReadout{induction: vec![0.1], ..Default::default()};
+        let s       = readout.share(&[100, 0], None);
+        assert_float_eq!(s.iter().sum::<f64>(), 90.0, abs<=1e-9);
+    }
+
+    #[test]
+    fn gain_adc_threshold_saturation() {
+        let readout = WireReadout{ gain: 2.0, threshold: 5, electrons_per_adc: 4.0, adc_bits: 6
+                                 , ..Default::default()};
+        // 2 -> 4 e -> 1 ADC (suppressed), 20 -> 40 e -> 10 ADC, 1000 -> saturates at 63
+        assert_eq!(readout.digitize(&[2, 20, 1000], None), vec![0, 10, 63]);
+    }
+
+    #[test]
+    fn wide_adc() {
+        for adc_bits in [63, 64, 100] {
+            let readout = WireReadout{adc_bits, ..Default::default()};
+            assert_eq!(readout.digitize(&[1000], None), vec![1000]);
+        }
+    }
+
+    #[test]
+    fn calibration_map() {
+        let entries = [ ChannelCalib{alive: false, ..ChannelCalib::ideal(0)}
+                      , ChannelCalib{gain : 0.5  , ..ChannelCalib::ideal(1)}
+                      ];
+        let calib   = ChannelMap::new(&entries, 3).unwrap();
+        let readout = WireReadout{induction: vec![0.1], ..Default::default()};
+        // the dead wire still induces on its neighbour
+        assert_eq!(readout.digitize(&[100, 100, 0], Some(&calib)), vec![0, 50, 5]);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(WireReadout::default().validate().is_ok());
+        assert!(WireReadout{induction: vec![0.3, 0.2], ..Default::default()}.validate().is_ok());
+        let bad = [ WireReadout{induction: vec![0.3, 0.25]     , ..Default::default()}
+                  , WireReadout{induction: vec![0.1, -0.05]    , ..Default::default()}
+                  , WireReadout{electrons_per_adc: 0.0         , ..Default::default()}
+                  , WireReadout{electrons_per_adc: -2.0        , ..Default::default()}
+                  , WireReadout{electrons_per_adc: f64::NAN    , ..Default::default()}
+                  ];
+        for readout in bad {
+            assert!(readout.validate().is_err(), "{readout:?}");
+        }
+    }
+
+    #[test]
+    fn noise_spread() {
+        crate::random::seed(33);
+        let readout = WireReadout{noise: 10.0, ..Default::default()};
+        let values : Vec<f64> = (0..2000).map(|_| readout.digitize(&[1000], None)[0] as f64).collect();
+        let mean   = values.iter().sum::<f64>() / values.len() as f64;
+        let var    = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        assert!((mean - 1000.0).abs() < 2.0, "mean {mean}");
+        assert!((var.sqrt() - 10.0).abs() < 1.5, "std {}", var.sqrt());
+    }
+}
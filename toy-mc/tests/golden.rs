@@ -0,0 +1,126 @@
+//! Regression tests comparing a small seeded simulation of `conf/test.toml`
+//! with the output committed in `tests/golden`. When the output changes on
+//! purpose, regenerate the files with
+//!
+//!     UPDATE_GOLDEN=1 cargo test --test golden
+//!
+//! and commit them along with the change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toymc::{SimConfig, Simulator};
+use toymc::io::{Table, Writer, read_table, waveform_writer, writer};
+use toymc::random::seed;
+
+const SEED    : u64   = 1234;
+const N_EVENTS: usize = 2;
+
+/// Metadata entries that depend on the run and not only on the events.
+const VOLATILE: [&str; 4] = ["toymc_version", "git_hash", "start", "end"];
+
+fn golden(filename: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(filename)
+}
+
+fn bless() -> bool {
+    std::env::var_os("UPDATE_GOLDEN").is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+/// `conf/test.toml` with the time response of the timing preset, so that
+/// waveforms are written too. The output directory is recorded in the
+/// metadata, so it is fixed rather than the one written to.
+fn config() -> SimConfig {
+    let base   = SimConfig::new("conf/test.toml").unwrap();
+    let timing = SimConfig::new("preset:timing").unwrap().timing;
+    SimConfig{ timing
+             , n_events: N_EVENTS
+             , seed    : Some(SEED)
+             , output  : "golden/".to_owned()
+             , ..base
+             }
+}
+
+/// Simulates the events of `conf`, writing images and waveforms in `format`
+/// to `dir`, and returns their paths.
+fn simulate(conf: &SimConfig, dir: &Path, format: Writer, extension: &str) -> [PathBuf; 2] {
+    let images    = dir.join(format!("images.{extension}"));
+    let waveforms = dir.join(format!("waveforms.{extension}"));
+    seed(conf.seed.unwrap());
+    {
+        let mut sim         = Simulator::new(conf).unwrap();
+        let mut write_event = writer         (images   .to_str().unwrap(), format, conf, sim.layout()).unwrap();
+        let mut write_wfs   = waveform_writer(waveforms.to_str().unwrap(), format, conf).unwrap();
+        for ievt in 0..conf.n_events {
+            let event = sim.simulate(ievt, None);
+            write_event(&event).unwrap();
+            write_wfs  (&event).unwrap();
+        }
+    } // files are completed when the writers are dropped
+    [images, waveforms]
+}
+
+/// Compares the tables numerically, with their stable metadata.
+fn assert_same(name: &str, table: &Table, expected: &Table) {
+    assert_eq!(table.names, expected.names, "{name}: columns differ");
+    assert_same_values(name, table, expected, 1e-9);
+    assert_eq!(stable_metadata(table), stable_metadata(expected), "{name}: metadata differs");
+}
+
+/// Values equal within `tolerance`, relative above 1.
+fn assert_same_values(name: &str, table: &Table, expected: &Table, tolerance: f64) {
+    assert_eq!(table.rows.len(), expected.rows.len(), "{name}: number of rows differs");
+    for (i, (row, exp)) in table.rows.iter().zip(&expected.rows).enumerate() {
+        for ((col, v), e) in table.names.iter().zip(row).zip(exp) {
+            let same = (v - e).abs() <= tolerance * e.abs().max(1.0) || (v.is_nan() && e.is_nan());
+            assert!(same, "{name}: row {i}, column {col}: {v} instead of {e}. \
+                           If the change is intended, regenerate with UPDATE_GOLDEN=1");
+        }
+    }
+}
+
+fn stable_metadata(table: &Table) -> Vec<(&String, &String)> {
+    let mut entries = table.metadata.iter().filter(|(k, _)| !VOLATILE.contains(&k.as_str())).collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
+fn check(format: Writer, extension: &str) {
+    let dir  = tempfile::tempdir().unwrap();
+    let conf = config();
+    for path in simulate(&conf, dir.path(), format, extension) {
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        let expected = golden(filename);
+        if bless() {
+            fs::create_dir_all(expected.parent().unwrap()).unwrap();
+            fs::copy(&path, &expected).unwrap();
+            continue
+        }
+        assert!(expected.exists(), "{} is missing, generate it with UPDATE_GOLDEN=1", expected.display());
+        let table    = read_table(path    .to_str().unwrap(), format).unwrap();
+        let golden   = read_table(expected.to_str().unwrap(), format).unwrap();
+        assert_same(filename, &table, &golden);
+    }
+}
+
+#[test]
+fn csv_output() {
+    check(Writer::Csv, "csv");
+}
+
+#[test]
+fn feather_output() {
+    check(Writer::Feather, "feather");
+}
+
+/// Both formats hold the same values, under their own column names.
+#[test]
+fn formats_agree() {
+    if bless() { return } // compared once written
+    for name in ["images", "waveforms"] {
+        let csv     = read_table(golden(&format!("{name}.csv"    )).to_str().unwrap(), Writer::Csv    ).unwrap();
+        let feather = read_table(golden(&format!("{name}.feather")).to_str().unwrap(), Writer::Feather).unwrap();
+        assert_eq!(feather.names.len(), csv.names.len(), "{name}: number of columns differs");
+        assert_same_values(name, &feather, &csv, 1e-6); // single precision in Feather
+    }
+}